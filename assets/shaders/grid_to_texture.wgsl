@@ -7,20 +7,24 @@ var particle_grid: texture_2d<u32>;
 #endif
 
 @group(0) @binding(1)
-var color_map: texture_1d<f32>;
+var color_map: texture_2d<f32>;
 
-#ifdef BINDLESS
-@group(0) @binding(2)
-var color_texture: binding_array<texture_storage_2d<rgba32float, write> >;
-#else
+// Atlas with a slot for every chunk
 @group(0) @binding(2)
-var color_texture: texture_storage_2d<rgba32float, write>;
-#endif
+var color_texture: texture_storage_2d<rgba16float, write>;
+
+// Atlas slot of every chunk in the dispatch
+@group(0) @binding(3)
+var<storage, read> chunk_slots: array<u32>;
 
 fn extract_material(particle_value: u32) -> u32 {
     return (particle_value >> 10) & 0x3FF; // Shift right by 10 bits and mask with 0x3FF to get 10 bits representing the material
 }
 
+fn extract_color_seed(particle_value: u32) -> u32 {
+    return (particle_value >> 20) & 0xFF; // Bits 20 to 27 hold the color seed
+}
+
 @compute @workgroup_size(8, 8, 1)
 fn render_grid(@builtin(global_invocation_id) invocation_id: vec3<u32>) {
     let location = vec2<i32>(i32(invocation_id.x), i32(invocation_id.y));
@@ -29,16 +33,25 @@ fn render_grid(@builtin(global_invocation_id) invocation_id: vec3<u32>) {
     let index = i32(invocation_id.z);
     let particle_value = textureLoad(particle_grid[index], location, 0).x; // Load the entire particle value
 #else
+    let index = 0;
     let particle_value = textureLoad(particle_grid, location, 0).x; // Load the entire particle value
 #endif
 
     let material = extract_material(particle_value); // Extract material from the particle value
 
-    var color = textureLoad(color_map, i32(material), 0); // Use extracted material to load the color
-
-#ifdef BINDLESS
-    textureStore(color_texture[index], location, color);
-#else
-    textureStore(color_texture, location, color);
-#endif
+    let color_seed = extract_color_seed(particle_value);
+
+    // Pick the color from the material's gradient, the first row of the color map holds the start
+    // and the second row the end of the gradient
+    let gradient_start = textureLoad(color_map, vec2<i32>(i32(material), 0), 0);
+    let gradient_end = textureLoad(color_map, vec2<i32>(i32(material), 1), 0);
+    var color = mix(gradient_start, gradient_end, f32(color_seed) / 255.0);
+
+    // Slots are laid out like the particle grid textures, with a row per x coordinate
+    let slot = chunk_slots[index];
+    let slot_origin = vec2<i32>(
+        i32(slot % #{ATLAS_SLOTS_PER_ROW}u),
+        i32(slot / #{ATLAS_SLOTS_PER_ROW}u)
+    ) * #{CHUNK_SIZE};
+    textureStore(color_texture, slot_origin + location, color);
 }
@@ -0,0 +1,14 @@
+#import bevy_sprite::mesh2d_vertex_output::VertexOutput
+
+@group(2) @binding(0)
+var light_map: texture_2d<f32>;
+
+@group(2) @binding(1)
+var light_map_sampler: sampler;
+
+// Depending on the layer, the pipeline multiplies the chunk sprites below by this color or adds
+// it to them
+@fragment
+fn fragment(mesh: VertexOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(textureSample(light_map, light_map_sampler, mesh.uv).rgb, 1.0);
+}
@@ -1,7 +1,7 @@
 use bevy::{
     ecs::{
         component::Component,
-        system::{Query, ResMut, Resource},
+        system::{Query, Res, ResMut, Resource},
     },
     math::IVec2,
     utils::HashMap,
@@ -10,8 +10,10 @@ use rand::seq::SliceRandom;
 use smallvec::SmallVec;
 
 use crate::{
-    falling_sand::{ChunkPosition, FallingSandRng},
+    falling_sand::{ChunkPosition, FallingSandRng, FallingSandSettings},
     process_chunks::PROCESSING_LIMIT,
+    time_control::SimulationTick,
+    visible_chunks::VisibleChunks,
 };
 
 #[derive(Component)]
@@ -34,7 +36,7 @@ impl ActiveChunks {
     }
 }
 
-fn chunk_pos_pass_index(pos: &IVec2) -> i32 {
+pub fn chunk_pos_pass_index(pos: &IVec2) -> i32 {
     let positive_mod = |n: i32, m: i32| ((n % m) + m) % m;
     let x = positive_mod(pos.x, 3);
     let y = positive_mod(pos.y, 3);
@@ -45,6 +47,9 @@ pub fn gather_active_chunks(
     mut active_chunks: ResMut<ActiveChunks>,
     active_chunks_query: Query<(&ChunkActive, &ChunkPosition)>,
     mut rng: ResMut<FallingSandRng>,
+    visible_chunks: Res<VisibleChunks>,
+    simulation_tick: Res<SimulationTick>,
+    falling_sand_settings: Res<FallingSandSettings>,
 ) {
     let ActiveChunks {
         ref mut passes,
@@ -55,6 +60,9 @@ pub fn gather_active_chunks(
     chunks.extend(
         active_chunks_query
             .iter()
+            .filter(|(_, pos)| {
+                visible_chunks.is_simulated(pos.0, simulation_tick.0, &falling_sand_settings)
+            })
             .map(|(_, pos)| (pos.0, chunk_pos_pass_index(&pos.0) as u8)),
     );
 
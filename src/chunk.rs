@@ -2,10 +2,10 @@ use std::sync::{Arc, RwLock};
 
 use bevy::{
     ecs::component::Component,
-    math::IVec2,
+    math::{IRect, IVec2},
     prelude::{Deref, DerefMut},
 };
-use rand::rngs::StdRng;
+use rand::{rngs::StdRng, Rng};
 
 use crate::{
     material::Material,
@@ -31,6 +31,8 @@ pub struct ChunkData {
     iter_rng: StdRng,
     rng: StdRng,
     dirty: bool,
+    /// Particles that changed since the chunk's particle grid texture was last written.
+    texture_dirty_rect: Option<IRect>,
 }
 
 impl ChunkData {
@@ -43,6 +45,7 @@ impl ChunkData {
             iter_rng: rng.clone(),
             rng,
             dirty: false,
+            texture_dirty_rect: None,
         }
     }
 
@@ -50,6 +53,8 @@ impl ChunkData {
         &self.particles
     }
 
+    /// Changes made through the grid aren't tracked in the texture dirty rectangle, only use it
+    /// for changes that don't show, like the particles' dirty flags.
     pub fn particles_mut(&mut self) -> &mut ParticleGrid {
         &mut self.particles
     }
@@ -73,6 +78,9 @@ impl ChunkData {
         self.get_particle_mut(a).unwrap().set_dirty(true);
         self.get_particle_mut(b).unwrap().set_dirty(true);
 
+        self.mark_texture_dirty(a);
+        self.mark_texture_dirty(b);
+
         // Swap the particles
         self.particles
             .array_mut()
@@ -85,15 +93,21 @@ impl ChunkData {
         self.particles.array().get((x as usize, y as usize))
     }
 
-    pub fn get_particle_mut(&mut self, IVec2 { x, y }: IVec2) -> Option<&mut Particle> {
+    pub fn get_particle_mut(&mut self, position: IVec2) -> Option<&mut Particle> {
         self.dirty = true;
+        self.mark_texture_dirty(position);
+        let IVec2 { x, y } = position;
         self.particles.array_mut().get_mut((x as usize, y as usize))
     }
 
+    /// Replace the particle at `position` with a new particle of `material`, which gets a new
+    /// color seed.
     pub fn set_particle_material(&mut self, position: IVec2, material: Material) {
         self.dirty = true;
+        let color_seed = self.rng.gen();
         let particle = self.get_particle_mut(position).unwrap();
         particle.set_material(material);
+        particle.set_color_seed(color_seed);
         particle.set_dirty(true);
     }
 
@@ -113,4 +127,55 @@ impl ChunkData {
     pub fn set_dirty(&mut self, dirty: bool) {
         self.dirty = dirty;
     }
+
+    fn mark_texture_dirty(&mut self, position: IVec2) {
+        if self
+            .particles
+            .array()
+            .get((position.x as usize, position.y as usize))
+            .is_none()
+        {
+            return;
+        }
+        self.texture_dirty_rect = Some(match self.texture_dirty_rect {
+            Some(rect) => rect.union_point(position),
+            None => IRect::from_corners(position, position),
+        });
+    }
+
+    /// Mark every particle as changed, so the whole chunk texture is written again.
+    pub fn set_texture_dirty(&mut self) {
+        self.texture_dirty_rect = Some(IRect::from_corners(IVec2::ZERO, self.size() - 1));
+    }
+
+    /// Rectangle with inclusive corners around the particles that changed since the last call.
+    pub fn take_texture_dirty_rect(&mut self) -> Option<IRect> {
+        self.texture_dirty_rect.take()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rand::SeedableRng;
+
+    use super::*;
+
+    #[test]
+    fn test_texture_dirty_rect_covers_changes() {
+        let chunk = Chunk::new_with_material((8, 8), Material::Air, StdRng::seed_from_u64(0));
+        let mut chunk_data = chunk.write().unwrap();
+        assert_eq!(chunk_data.take_texture_dirty_rect(), None);
+
+        chunk_data.set_particle_material(IVec2::new(2, 5), Material::Sand);
+        chunk_data.swap_particles(IVec2::new(4, 1), IVec2::new(4, 2));
+        assert_eq!(
+            chunk_data.take_texture_dirty_rect(),
+            Some(IRect::new(2, 1, 4, 5))
+        );
+        assert_eq!(chunk_data.take_texture_dirty_rect(), None);
+
+        // Positions outside the chunk don't grow the rectangle
+        assert!(chunk_data.get_particle_mut(IVec2::new(-1, 9)).is_none());
+        assert_eq!(chunk_data.take_texture_dirty_rect(), None);
+    }
 }
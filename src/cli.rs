@@ -0,0 +1,130 @@
+use std::path::PathBuf;
+
+pub const USAGE: &str = "\
+Usage: falling-sand [OPTIONS]
+
+Options:
+  --cpu-render                   Render chunks on the CPU instead of with a compute shader
+  --distant-tick-interval=TICKS  Simulate chunks far off screen only every TICKS ticks
+  --stamp-dir=DIR                Directory user stamps are saved to and loaded from
+  --import=PNG                   Start in a world imported from an image
+  --export-scale=PIXELS          Width and height in pixels of a tile in exported images
+  --record-png                   Record PNG frames instead of an animated GIF
+  -h, --help                     Print this help";
+
+/// Command line options, see `USAGE`.
+#[derive(Debug, Default, PartialEq)]
+pub struct Args {
+    pub cpu_render: bool,
+    pub distant_tick_interval: Option<u32>,
+    pub stamp_dir: Option<PathBuf>,
+    pub import: Option<PathBuf>,
+    pub export_scale: Option<u32>,
+    // The web build can't record
+    #[cfg_attr(target_arch = "wasm32", allow(dead_code))]
+    pub record_png: bool,
+    pub help: bool,
+}
+
+impl Args {
+    /// Parse the arguments after the program name, failing on unknown options and malformed
+    /// values.
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Args, String> {
+        let mut parsed = Args::default();
+        for arg in args {
+            let (name, value) = match arg.split_once('=') {
+                Some((name, value)) => (name, Some(value)),
+                None => (arg.as_str(), None),
+            };
+            match (name, value) {
+                ("--cpu-render", None) => parsed.cpu_render = true,
+                ("--record-png", None) => parsed.record_png = true,
+                ("-h" | "--help", None) => parsed.help = true,
+                ("--distant-tick-interval", Some(value)) => {
+                    parsed.distant_tick_interval = Some(parse_positive(name, value)?);
+                }
+                ("--export-scale", Some(value)) => {
+                    parsed.export_scale = Some(parse_positive(name, value)?);
+                }
+                ("--stamp-dir", Some(value)) => parsed.stamp_dir = Some(parse_path(name, value)?),
+                ("--import", Some(value)) => parsed.import = Some(parse_path(name, value)?),
+                ("--cpu-render" | "--record-png" | "-h" | "--help", Some(_)) => {
+                    return Err(format!("{} doesn't take a value", name));
+                }
+                (
+                    "--distant-tick-interval" | "--export-scale" | "--stamp-dir" | "--import",
+                    None,
+                ) => {
+                    return Err(format!("{} needs a value, as in {}=...", name, name));
+                }
+                _ => return Err(format!("Unknown option {}", arg)),
+            }
+        }
+        Ok(parsed)
+    }
+
+    /// Parse the arguments of this process, exiting with the usage on `--help` or an error.
+    pub fn from_env() -> Args {
+        match Args::parse(std::env::args().skip(1)) {
+            Ok(args) if args.help => {
+                println!("{}", USAGE);
+                std::process::exit(0);
+            }
+            Ok(args) => args,
+            Err(error) => {
+                eprintln!("{}\n\n{}", error, USAGE);
+                std::process::exit(2);
+            }
+        }
+    }
+}
+
+fn parse_positive(name: &str, value: &str) -> Result<u32, String> {
+    match value.parse() {
+        Ok(number) if number > 0 => Ok(number),
+        _ => Err(format!(
+            "{} must be a whole number above 0, not {:?}",
+            name, value
+        )),
+    }
+}
+
+fn parse_path(name: &str, value: &str) -> Result<PathBuf, String> {
+    if value.is_empty() {
+        return Err(format!("{} needs a path", name));
+    }
+    Ok(value.into())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Args, String> {
+        Args::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn test_parse_options() {
+        assert_eq!(parse(&[]), Ok(Args::default()));
+        assert_eq!(
+            parse(&["--cpu-render", "--export-scale=4", "--import=world.png"]),
+            Ok(Args {
+                cpu_render: true,
+                export_scale: Some(4),
+                import: Some("world.png".into()),
+                ..Args::default()
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_rejects_bad_options() {
+        assert!(parse(&["--cpu"]).is_err());
+        assert!(parse(&["--export-scale=0"]).is_err());
+        assert!(parse(&["--distant-tick-interval=often"]).is_err());
+        assert!(parse(&["--stamp-dir"]).is_err());
+        assert!(parse(&["--import="]).is_err());
+        assert!(parse(&["--record-png=yes"]).is_err());
+    }
+}
@@ -1,13 +1,24 @@
 use bevy::{
     app::{App, Plugin, Update},
-    ecs::system::{Query, ResMut, Resource},
+    ecs::{
+        event::EventReader,
+        schedule::IntoSystemConfigs,
+        system::{Query, Res, ResMut, Resource},
+    },
     gizmos::gizmos::Gizmos,
+    input::{
+        gamepad::{GamepadAxis, GamepadAxisType, Gamepads},
+        Axis,
+    },
     math::Vec2,
     reflect::Reflect,
     render::{camera::Camera, color::Color},
+    time::Time,
     transform::components::GlobalTransform,
-    window::Window,
+    window::{CursorMoved, Window},
 };
+
+use crate::input_map::gamepad_stick;
 // use bevy_inspector_egui::quick::ResourceInspectorPlugin;
 
 pub struct CursorWorldPositionPlugin;
@@ -16,8 +27,12 @@ impl Plugin for CursorWorldPositionPlugin {
     fn build(&self, app: &mut App) {
         app.register_type::<CursorWorldPosition>()
             .insert_resource(CursorWorldPosition(Vec2::ZERO))
+            .init_resource::<VirtualCursor>()
             // .add_plugins(ResourceInspectorPlugin::<CursorWorldPosition>::default())
-            .add_systems(Update, update_cursor_world_position);
+            .add_systems(
+                Update,
+                (move_virtual_cursor, update_cursor_world_position).chain(),
+            );
     }
 }
 
@@ -30,17 +45,62 @@ impl CursorWorldPosition {
     }
 }
 
+/// Window position of the cursor moved with a gamepad stick. It replaces the mouse cursor until
+/// the mouse is moved again.
+#[derive(Resource, Default)]
+struct VirtualCursor(Option<Vec2>);
+
+/// Speed of the virtual cursor at full stick deflection, in logical pixels per second.
+const VIRTUAL_CURSOR_SPEED: f32 = 400.;
+
+fn move_virtual_cursor(
+    mut virtual_cursor: ResMut<VirtualCursor>,
+    mut cursor_moved_events: EventReader<CursorMoved>,
+    gamepads: Res<Gamepads>,
+    gamepad_axes: Res<Axis<GamepadAxis>>,
+    window: Query<&Window>,
+    time: Res<Time>,
+) {
+    if cursor_moved_events.read().count() > 0 {
+        virtual_cursor.0 = None;
+    }
+
+    let stick = gamepad_stick(
+        &gamepads,
+        &gamepad_axes,
+        GamepadAxisType::LeftStickX,
+        GamepadAxisType::LeftStickY,
+    );
+    if stick == Vec2::ZERO {
+        return;
+    }
+    let Ok(window) = window.get_single() else {
+        return;
+    };
+
+    let window_size = Vec2::new(window.width(), window.height());
+    let position = virtual_cursor
+        .0
+        .or(window.cursor_position())
+        .unwrap_or(window_size / 2.);
+    // Window coordinates have y pointing down
+    let movement = Vec2::new(stick.x, -stick.y) * VIRTUAL_CURSOR_SPEED * time.delta_seconds();
+    virtual_cursor.0 = Some((position + movement).clamp(Vec2::ZERO, window_size));
+}
+
 fn update_cursor_world_position(
+    virtual_cursor: Res<VirtualCursor>,
     window: Query<&Window>,
     camera_query: Query<(&GlobalTransform, &Camera)>,
     mut cursor_world_position_query: ResMut<CursorWorldPosition>,
     mut gizmos: Gizmos,
 ) {
-    let Some(cursor_position) = window
-        .get_single()
-        .ok()
-        .and_then(|window| window.cursor_position())
-    else {
+    let Some(cursor_position) = virtual_cursor.0.or_else(|| {
+        window
+            .get_single()
+            .ok()
+            .and_then(|window| window.cursor_position())
+    }) else {
         return;
     };
 
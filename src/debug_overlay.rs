@@ -0,0 +1,306 @@
+use bevy::{
+    app::{App, Plugin, PostUpdate, Startup, Update},
+    asset::{Assets, Handle},
+    ecs::{
+        change_detection::{DetectChanges, DetectChangesMut},
+        component::Component,
+        schedule::IntoSystemConfigs,
+        system::{Commands, Query, Res, ResMut, Resource},
+    },
+    math::{IVec2, UVec2},
+    prelude::{default, Color},
+    render::{
+        render_asset::RenderAssetUsages,
+        render_resource::{Extent3d, TextureDimension, TextureFormat},
+        texture::Image,
+        view::Visibility,
+    },
+    sprite::{Sprite, SpriteBundle},
+    transform::components::Transform,
+};
+
+use crate::{
+    active_chunks::chunk_pos_pass_index,
+    chunk::{Chunk, ChunkData},
+    consts::CHUNK_SIZE,
+    fall::MOMENTUM_GAIN,
+    falling_sand::{ChunkPosition, FallingSandSettings},
+    input_map::{Action, ActionInput},
+    visible_chunks::{update_visible_chunks, VisibleChunks},
+};
+
+pub struct DebugOverlayPlugin;
+
+impl Plugin for DebugOverlayPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<DebugOverlayMode>()
+            .add_systems(Startup, spawn_debug_overlay)
+            .add_systems(Update, cycle_debug_overlay_mode)
+            .add_systems(
+                PostUpdate,
+                update_debug_overlay.after(update_visible_chunks),
+            );
+    }
+}
+
+#[derive(Resource, Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DebugOverlayMode {
+    #[default]
+    Off,
+    Velocity,
+    Momentum,
+    Dirty,
+    PassIndex,
+}
+
+impl DebugOverlayMode {
+    fn next(self) -> Self {
+        match self {
+            DebugOverlayMode::Off => DebugOverlayMode::Velocity,
+            DebugOverlayMode::Velocity => DebugOverlayMode::Momentum,
+            DebugOverlayMode::Momentum => DebugOverlayMode::Dirty,
+            DebugOverlayMode::Dirty => DebugOverlayMode::PassIndex,
+            DebugOverlayMode::PassIndex => DebugOverlayMode::Off,
+        }
+    }
+}
+
+/// Opacity of the overlay on top of the particle colors.
+const OVERLAY_ALPHA: f32 = 0.7;
+
+/// Sprite showing the overlay of the visible chunks, with a texel per tile.
+#[derive(Component)]
+struct DebugOverlay {
+    /// Chunks the overlay image covers.
+    visible_chunks: VisibleChunks,
+}
+
+fn cycle_debug_overlay_mode(
+    action_input: ActionInput,
+    mut debug_overlay_mode: ResMut<DebugOverlayMode>,
+) {
+    if action_input.just_pressed(Action::CycleDebugOverlay) {
+        *debug_overlay_mode = debug_overlay_mode.next();
+    }
+}
+
+fn create_overlay_image(size: UVec2) -> Image {
+    Image::new_fill(
+        Extent3d {
+            width: size.x.max(1),
+            height: size.y.max(1),
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        &[0u8; 4],
+        TextureFormat::Rgba8UnormSrgb,
+        RenderAssetUsages::MAIN_WORLD | RenderAssetUsages::RENDER_WORLD,
+    )
+}
+
+fn spawn_debug_overlay(mut commands: Commands, mut images: ResMut<Assets<Image>>) {
+    commands.spawn((
+        DebugOverlay {
+            visible_chunks: VisibleChunks::default(),
+        },
+        SpriteBundle {
+            texture: images.add(create_overlay_image(UVec2::ONE)),
+            // Above the light map
+            transform: Transform::from_xyz(0., 0., 2.),
+            visibility: Visibility::Hidden,
+            ..default()
+        },
+    ));
+}
+
+/// Redraw the overlay of the visible chunks that changed, and the whole overlay when the mode or
+/// the visible chunks change.
+fn update_debug_overlay(
+    debug_overlay_mode: Res<DebugOverlayMode>,
+    visible_chunks: Res<VisibleChunks>,
+    falling_sand_settings: Res<FallingSandSettings>,
+    mut images: ResMut<Assets<Image>>,
+    chunk_query: Query<(&Chunk, &ChunkPosition)>,
+    mut overlay_query: Query<(
+        &mut DebugOverlay,
+        &Handle<Image>,
+        &mut Sprite,
+        &mut Transform,
+        &mut Visibility,
+    )>,
+) {
+    let Ok((mut overlay, image, mut sprite, mut transform, mut visibility)) =
+        overlay_query.get_single_mut()
+    else {
+        return;
+    };
+    let chunk_count = visible_chunks.max - visible_chunks.min + IVec2::ONE;
+    if *debug_overlay_mode == DebugOverlayMode::Off || chunk_count.cmple(IVec2::ZERO).any() {
+        visibility.set_if_neq(Visibility::Hidden);
+        return;
+    }
+    visibility.set_if_neq(Visibility::Inherited);
+
+    let redraw_all = debug_overlay_mode.is_changed()
+        || overlay.visible_chunks != *visible_chunks
+        || visibility.is_changed();
+    // Chunks that didn't change last tick still show an up to date overlay
+    let changed_chunks = chunk_query
+        .iter()
+        .filter(|(chunk, position)| {
+            visible_chunks.contains(position.0) && (redraw_all || chunk.read().unwrap().is_dirty())
+        })
+        .collect::<Vec<_>>();
+    if !redraw_all && changed_chunks.is_empty() {
+        return;
+    }
+    let Some(image) = images.get_mut(image) else {
+        return;
+    };
+
+    if redraw_all {
+        // Also clears the tiles without a chunk
+        *image = create_overlay_image((chunk_count * CHUNK_SIZE).as_uvec2());
+        let chunk_world_size = (CHUNK_SIZE * falling_sand_settings.tile_size as i32) as f32;
+        let center = (visible_chunks.min + visible_chunks.max).as_vec2() / 2. * chunk_world_size;
+        transform.translation = center.extend(transform.translation.z);
+        sprite.custom_size = Some(chunk_count.as_vec2() * chunk_world_size);
+        overlay.visible_chunks = *visible_chunks;
+    }
+
+    for (chunk, chunk_position) in changed_chunks {
+        write_overlay_pixels(
+            &mut image.data,
+            &visible_chunks,
+            &chunk.read().unwrap(),
+            chunk_position.0,
+            *debug_overlay_mode,
+        );
+    }
+}
+
+/// Write the overlay of the chunk at `chunk_position` into the RGBA `pixels` of an overlay image
+/// covering `visible_chunks`, which starts at the top row.
+fn write_overlay_pixels(
+    pixels: &mut [u8],
+    visible_chunks: &VisibleChunks,
+    chunk_data: &ChunkData,
+    chunk_position: IVec2,
+    mode: DebugOverlayMode,
+) {
+    let attributes = chunk_data.attributes();
+    let pass_index = chunk_pos_pass_index(&chunk_position);
+    let width = (visible_chunks.max.x - visible_chunks.min.x + 1) * CHUNK_SIZE;
+    // Texel of the bottom left tile of the chunk
+    let origin = IVec2::new(
+        (chunk_position.x - visible_chunks.min.x) * CHUNK_SIZE,
+        (visible_chunks.max.y - chunk_position.y + 1) * CHUNK_SIZE - 1,
+    );
+
+    for ((x, y), particle) in chunk_data.particles().array().indexed_iter() {
+        let color = match mode {
+            DebugOverlayMode::Off => Color::NONE,
+            DebugOverlayMode::Velocity => attributes
+                .velocity
+                .get(particle.id())
+                .map_or(Color::NONE, |&velocity| velocity_color(velocity)),
+            DebugOverlayMode::Momentum => attributes
+                .momentum
+                .get(particle.id())
+                .map_or(Color::NONE, |&momentum| momentum_color(momentum)),
+            DebugOverlayMode::Dirty => {
+                if particle.dirty() {
+                    Color::RED.with_a(OVERLAY_ALPHA)
+                } else {
+                    Color::NONE
+                }
+            }
+            DebugOverlayMode::PassIndex => pass_index_color(pass_index),
+        };
+        let texel = origin + IVec2::new(x as i32, -(y as i32));
+        let index = (texel.y * width + texel.x) as usize * 4;
+        pixels[index..index + 4].copy_from_slice(&color.as_rgba_u8());
+    }
+}
+
+/// Hue encodes the direction of the velocity, no velocity is left transparent.
+fn velocity_color(velocity: IVec2) -> Color {
+    if velocity == IVec2::ZERO {
+        return Color::NONE;
+    }
+    let angle = (velocity.y as f32).atan2(velocity.x as f32).to_degrees();
+    Color::hsla(angle.rem_euclid(360.), 0.9, 0.5, OVERLAY_ALPHA)
+}
+
+fn momentum_color(momentum: u16) -> Color {
+    if momentum == 0 {
+        return Color::NONE;
+    }
+    let magnitude = (momentum as f32 / MOMENTUM_GAIN as f32).min(1.);
+    Color::rgba(magnitude, 0., 1. - magnitude, OVERLAY_ALPHA)
+}
+
+fn pass_index_color(pass_index: i32) -> Color {
+    Color::hsla(pass_index as f32 * 40., 0.8, 0.5, OVERLAY_ALPHA * 0.5)
+}
+
+#[cfg(test)]
+mod test {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use crate::material::Material;
+
+    use super::*;
+
+    #[test]
+    fn test_mode_cycles_back_to_off() {
+        let mut mode = DebugOverlayMode::Off.next();
+        let mut steps = 1;
+        while mode != DebugOverlayMode::Off {
+            mode = mode.next();
+            steps += 1;
+        }
+        assert_eq!(steps, 5);
+    }
+
+    #[test]
+    fn test_overlay_pixels_are_placed_in_world_space() {
+        let visible_chunks = VisibleChunks {
+            min: IVec2::new(-1, 0),
+            max: IVec2::new(0, 1),
+        };
+        let chunk = Chunk::new_with_material(
+            (CHUNK_SIZE as usize, CHUNK_SIZE as usize),
+            Material::Air,
+            StdRng::seed_from_u64(0),
+        );
+        let mut chunk_data = chunk.write().unwrap();
+        chunk_data.set_particle_material(IVec2::new(2, 5), Material::Sand);
+
+        let width = 2 * CHUNK_SIZE as usize;
+        let mut pixels = vec![0; width * width * 4];
+        write_overlay_pixels(
+            &mut pixels,
+            &visible_chunks,
+            &chunk_data,
+            IVec2::ZERO,
+            DebugOverlayMode::Dirty,
+        );
+
+        // The chunk at the origin is the bottom right one, its rows go up from the bottom
+        let pixel = |x: usize, y: usize| &pixels[(y * width + x) * 4..(y * width + x + 1) * 4];
+        let dirty = Color::RED.with_a(OVERLAY_ALPHA).as_rgba_u8();
+        assert_eq!(pixel(CHUNK_SIZE as usize + 2, width - 1 - 5), dirty);
+        assert_eq!(pixels.chunks(4).filter(|pixel| *pixel == dirty).count(), 1);
+    }
+
+    #[test]
+    fn test_velocity_color_is_transparent_at_rest() {
+        assert_eq!(velocity_color(IVec2::ZERO), Color::NONE);
+        assert_ne!(
+            velocity_color(IVec2::new(1, 0)),
+            velocity_color(IVec2::new(0, 1))
+        );
+        assert_eq!(momentum_color(0), Color::NONE);
+    }
+}
@@ -6,7 +6,7 @@ use bevy::{
         component::Component,
         entity::Entity,
         event::EventReader,
-        query::{Changed, With},
+        query::{Changed, With, Without},
         schedule::{
             apply_deferred,
             common_conditions::{not, resource_exists},
@@ -14,12 +14,10 @@ use bevy::{
         },
         system::{Commands, Local, Query, Res, ResMut, Resource},
     },
+    gizmos::gizmos::Gizmos,
     hierarchy::{BuildChildren, ChildBuilder},
-    input::{
-        keyboard::KeyCode,
-        mouse::{MouseButton, MouseWheel},
-        ButtonInput,
-    },
+    input::mouse::MouseWheel,
+    log::warn,
     math::{IVec2, Vec2},
     prelude::{default, Color},
     reflect::Reflect,
@@ -27,13 +25,16 @@ use bevy::{
     time::{Time, Timer},
     ui::{
         node_bundles::{ButtonBundle, NodeBundle, TextBundle},
-        BackgroundColor, BorderColor, Display, FlexDirection, Interaction, JustifyContent, Style,
-        UiRect, Val,
+        AlignItems, BackgroundColor, BorderColor, Display, FlexDirection, Interaction,
+        JustifyContent, Style, UiRect, Val,
     },
-    utils::HashMap,
+    utils::HashSet,
 };
+use enum_map::{Enum, EnumMap};
 use itertools::Itertools;
 use line_drawing::Bresenham;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::collections::VecDeque;
 
 use crate::{
     chunk::Chunk,
@@ -41,8 +42,10 @@ use crate::{
     falling_sand::{ChunkCreationParams, ChunkPositions, FallingSandSet, FallingSandSettings},
     falling_sand_grid::FallingSandGridQuery,
     hovering_ui::{HoveringUiSet, UiFocused},
+    input_map::{Action, ActionInput},
     material::{Material, MaterialColor, MaterialIterator},
-    util::tile_pos_to_chunk_pos,
+    ui,
+    util::{above, below, left, right},
 };
 
 pub struct DrawToolPlugin;
@@ -63,12 +66,19 @@ impl bevy::app::Plugin for DrawToolPlugin {
     fn build(&self, app: &mut App) {
         app.init_resource::<CursorTilePosition>()
             .init_resource::<ToolState>()
+            .init_resource::<ShapeDragStart>()
+            .insert_resource(DrawToolRng(StdRng::seed_from_u64(0)))
             .add_systems(Startup, setup_ui)
             .add_systems(
                 Update,
                 (
                     cursor_tile_position_system,
-                    calculate_stroke,
+                    (
+                        calculate_stroke,
+                        calculate_fill,
+                        calculate_shape,
+                        pick_material,
+                    ),
                     apply_deferred,
                     spawn_chunk_under_stroke,
                     apply_deferred,
@@ -79,6 +89,14 @@ impl bevy::app::Plugin for DrawToolPlugin {
                     .in_set(DrawToolUpdateSet)
                     .in_set(DrawToolSet),
             )
+            .add_systems(
+                Update,
+                (
+                    draw_brush_preview.run_if(not(resource_exists::<UiFocused>)),
+                    cancel_shape_drag,
+                )
+                    .after(DrawToolUpdateSet),
+            )
             .add_systems(
                 FixedUpdate,
                 draw_particles
@@ -92,7 +110,12 @@ impl bevy::app::Plugin for DrawToolPlugin {
                     switch_tool_system,
                     material_button_system,
                     brush_size_system,
+                    spray_density_system,
                     brush_shape_picker_system,
+                    tool_picker_system,
+                    shape_fill_toggle_system,
+                    brush_mode_picker_system,
+                    brush_material_options_system,
                 )
                     .before(DrawToolUpdateSet)
                     .in_set(DrawToolPickerSet)
@@ -107,6 +130,12 @@ struct MaterialButton(Material);
 #[derive(Component)]
 struct BrushSizeText;
 
+#[derive(Component)]
+struct SprayDensityText;
+
+#[derive(Component)]
+struct EmitterRateText;
+
 #[derive(Component)]
 struct BrushShapePicker;
 
@@ -116,33 +145,21 @@ fn setup_ui(
     material_colors: Res<MaterialColor>,
     tool_state: Res<ToolState>,
 ) {
-    let font = asset_server.load("fonts/PublicPixel-z84yD.ttf");
+    let font = asset_server.load(ui::FONT_PATH);
     let node_style = Style {
         justify_content: JustifyContent::SpaceBetween,
-        border: UiRect::all(Val::Px(2.0)),
-        display: Display::Flex,
-        flex_direction: FlexDirection::Column,
-        padding: UiRect::all(Val::Px(2.0)),
-        margin: UiRect::all(Val::Px(2.0)),
-        ..default()
+        ..ui::panel_style()
     };
-    let border_color = (Color::GRAY * 1.8).into();
-    let background_color = Color::WHITE.into();
 
     commands
-        .spawn((
-            Interaction::default(),
-            NodeBundle {
-                style: node_style,
-                border_color,
-                background_color,
-                ..default()
-            },
-        ))
+        .spawn((Interaction::default(), ui::panel_bundle(node_style)))
         .with_children(|parent| {
             spawn_material_picker(parent, &font, &material_colors);
             spawn_brush_size_picker(parent, &font, &tool_state);
             spawn_brush_shape_picker(parent, &font);
+            spawn_tool_picker(parent, &font);
+            spawn_shape_fill_toggle(parent, &font, &tool_state);
+            spawn_brush_mode_picker(parent, &font, &material_colors);
         });
 }
 
@@ -160,22 +177,13 @@ fn spawn_material_picker(
         } else {
             Color::WHITE
         };
-        let border_color = if lightness > 0.5 {
-            material_color * 0.8
-        } else {
-            material_color * 1.2
-        };
+        let border_color = material_button_border_color(material_color);
 
         parent
             .spawn((
                 MaterialButton(material),
                 ButtonBundle {
-                    style: Style {
-                        margin: UiRect::all(Val::Px(2.0)),
-                        padding: UiRect::all(Val::Px(2.0)),
-                        border: UiRect::all(Val::Px(2.0)),
-                        ..default()
-                    },
+                    style: ui::button_style(),
                     border_color: border_color.into(),
                     background_color: material_color.into(),
                     ..default()
@@ -185,15 +193,22 @@ fn spawn_material_picker(
                 parent.spawn(TextBundle::from_section(
                     material.to_string(),
                     TextStyle {
-                        font: font.clone(),
                         color: text_color,
-                        ..default()
+                        ..ui::text_style(font)
                     },
                 ));
             });
     }
 }
 
+fn material_button_border_color(material_color: Color) -> Color {
+    if material_color.l() > 0.5 {
+        material_color * 0.8
+    } else {
+        material_color * 1.2
+    }
+}
+
 fn spawn_brush_size_picker(
     parent: &mut ChildBuilder,
     font: &Handle<Font>,
@@ -205,40 +220,24 @@ fn spawn_brush_size_picker(
         border: UiRect::all(Val::Px(2.0)),
         ..default()
     };
-    parent.spawn((
-        BrushSizeText,
+    let labels = [
+        ("Brush size:", tool_state.brush_size.to_string()),
+        ("Spray density:", percent_label(tool_state.spray_density)),
+        ("Emitter rate:", emitter_rate_label(tool_state.emitter_rate)),
+    ];
+    let [brush_size, spray_density, emitter_rate] = labels.map(|(label, value)| {
         TextBundle::from_sections([
-            TextSection::new(
-                "Brush size:",
-                TextStyle {
-                    font: font.clone(),
-                    color: Color::BLACK,
-                    ..default()
-                },
-            ),
-            TextSection::new(
-                tool_state.brush_size.to_string(),
-                TextStyle {
-                    font: font.clone(),
-                    color: Color::BLACK,
-                    ..default()
-                },
-            ),
+            TextSection::new(label, ui::text_style(font)),
+            TextSection::new(value, ui::text_style(font)),
         ])
-        .with_style(style),
-    ));
+        .with_style(style.clone())
+    });
+    parent.spawn((BrushSizeText, brush_size));
+    parent.spawn((SprayDensityText, spray_density));
+    parent.spawn((EmitterRateText, emitter_rate));
 }
 
 fn spawn_brush_shape_picker(parent: &mut ChildBuilder, font: &Handle<Font>) {
-    let button_style = Style {
-        margin: UiRect::all(Val::Px(2.0)),
-        padding: UiRect::all(Val::Px(2.0)),
-        border: UiRect::all(Val::Px(2.0)),
-        ..default()
-    };
-    let border_color = (Color::GRAY * 1.8).into();
-    let background_color = Color::WHITE.into();
-
     parent
         .spawn((
             BrushShapePicker,
@@ -254,33 +253,292 @@ fn spawn_brush_shape_picker(parent: &mut ChildBuilder, font: &Handle<Font>) {
             for (brush_shape, name) in [
                 (BrushShape::Rectangle, "Rect"),
                 (BrushShape::Circle, "Circle"),
+                (BrushShape::Spray, "Spray"),
             ] {
-                parent
-                    .spawn((
-                        brush_shape,
-                        ButtonBundle {
-                            style: button_style.clone(),
-                            border_color,
-                            background_color,
-                            ..default()
-                        },
-                    ))
-                    .with_children(|parent| {
-                        parent.spawn(TextBundle::from_section(
-                            name,
-                            TextStyle {
-                                font: font.clone(),
-                                color: Color::BLACK,
-                                ..default()
-                            },
-                        ));
-                    });
+                ui::spawn_text_button(parent, font, name, brush_shape);
             }
         });
 }
 
+fn spawn_tool_picker(parent: &mut ChildBuilder, font: &Handle<Font>) {
+    parent
+        .spawn(NodeBundle {
+            style: ui::button_row_style(),
+            ..default()
+        })
+        .with_children(|parent| {
+            for (tool, name) in [
+                (Tool::Brush, "Brush"),
+                (Tool::Fill, "Fill"),
+                (Tool::Line, "Line"),
+                (Tool::Rectangle, "Rect"),
+                (Tool::Ellipse, "Ellipse"),
+                (Tool::Select, "Select"),
+                (Tool::Paste, "Paste"),
+                (Tool::Emitter, "Emitter"),
+                (Tool::Sink, "Sink"),
+            ] {
+                ui::spawn_text_button(parent, font, name, tool);
+            }
+        });
+}
+
+#[derive(Component)]
+struct ShapeFillToggle;
+
+#[derive(Component)]
+struct ShapeFillToggleText;
+
+fn shape_fill_label(filled: bool) -> &'static str {
+    if filled {
+        "Shapes: filled"
+    } else {
+        "Shapes: outline"
+    }
+}
+
+fn spawn_shape_fill_toggle(
+    parent: &mut ChildBuilder,
+    font: &Handle<Font>,
+    tool_state: &Res<ToolState>,
+) {
+    parent
+        .spawn((ShapeFillToggle, ui::button_bundle()))
+        .with_children(|parent| {
+            parent.spawn((
+                ShapeFillToggleText,
+                TextBundle::from_section(
+                    shape_fill_label(tool_state.shape_filled),
+                    ui::text_style(font),
+                ),
+            ));
+        });
+}
+
+fn shape_fill_toggle_system(
+    interaction_query: Query<&Interaction, (Changed<Interaction>, With<ShapeFillToggle>)>,
+    mut text_query: Query<&mut Text, With<ShapeFillToggleText>>,
+    mut tool_state: ResMut<ToolState>,
+) {
+    for interaction in interaction_query.iter() {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+
+        tool_state.shape_filled = !tool_state.shape_filled;
+        for mut text in text_query.iter_mut() {
+            text.sections[0].value = shape_fill_label(tool_state.shape_filled).to_string();
+        }
+    }
+}
+
+#[derive(Component)]
+struct ReplaceMaterialRow;
+
+#[derive(Component)]
+struct ReplaceMaterialButton(Material);
+
+#[derive(Component)]
+struct ProtectMaterialButton(Material);
+
+fn spawn_brush_mode_picker(
+    parent: &mut ChildBuilder,
+    font: &Handle<Font>,
+    material_colors: &Res<MaterialColor>,
+) {
+    let row_style = Style {
+        align_items: AlignItems::Center,
+        ..ui::button_row_style()
+    };
+
+    parent
+        .spawn(NodeBundle {
+            style: row_style.clone(),
+            ..default()
+        })
+        .with_children(|parent| {
+            for (brush_mode, name) in [
+                (BrushMode::All, "All"),
+                (BrushMode::AirOnly, "Air only"),
+                (BrushMode::Replace, "Replace"),
+            ] {
+                ui::spawn_text_button(parent, font, name, brush_mode);
+            }
+        });
+
+    parent
+        .spawn((
+            ReplaceMaterialRow,
+            NodeBundle {
+                style: Style {
+                    display: Display::None,
+                    ..row_style.clone()
+                },
+                ..default()
+            },
+        ))
+        .with_children(|parent| {
+            spawn_material_swatches(parent, font, material_colors, "Replace:", |material| {
+                ReplaceMaterialButton(material)
+            });
+        });
+
+    parent
+        .spawn(NodeBundle {
+            style: row_style,
+            ..default()
+        })
+        .with_children(|parent| {
+            spawn_material_swatches(parent, font, material_colors, "Protect:", |material| {
+                ProtectMaterialButton(material)
+            });
+        });
+}
+
+/// Small material buttons behind a label, for picking materials for brush options.
+fn spawn_material_swatches<C: Component>(
+    parent: &mut ChildBuilder,
+    font: &Handle<Font>,
+    material_colors: &Res<MaterialColor>,
+    label: &str,
+    button_component: impl Fn(Material) -> C,
+) {
+    parent.spawn(TextBundle::from_section(
+        label,
+        TextStyle {
+            font_size: 8.0,
+            ..ui::text_style(font)
+        },
+    ));
+
+    for material in MaterialIterator::new() {
+        let material_color = material_colors.0[material];
+        let text_color = if material_color.l() > 0.5 {
+            Color::BLACK
+        } else {
+            Color::WHITE
+        };
+
+        parent
+            .spawn((
+                button_component(material),
+                ButtonBundle {
+                    style: Style {
+                        margin: UiRect::all(Val::Px(1.0)),
+                        padding: UiRect::all(Val::Px(1.0)),
+                        border: UiRect::all(Val::Px(2.0)),
+                        ..default()
+                    },
+                    border_color: material_color.into(),
+                    background_color: material_color.into(),
+                    ..default()
+                },
+            ))
+            .with_children(|parent| {
+                parent.spawn(TextBundle::from_section(
+                    material.to_string(),
+                    TextStyle {
+                        font_size: 8.0,
+                        color: text_color,
+                        ..ui::text_style(font)
+                    },
+                ));
+            });
+    }
+}
+
+fn brush_mode_picker_system(
+    mut brush_mode_button_query: Query<
+        (&Interaction, &BrushMode, &mut BorderColor),
+        Changed<Interaction>,
+    >,
+    mut background_color_query: Query<(&mut BackgroundColor, &BrushMode)>,
+    mut replace_material_row_query: Query<&mut Style, With<ReplaceMaterialRow>>,
+    mut tool_state: ResMut<ToolState>,
+) {
+    for (interaction, brush_mode, mut border_color) in brush_mode_button_query.iter_mut() {
+        match *interaction {
+            Interaction::Pressed => {
+                tool_state.brush_mode = *brush_mode;
+                border_color.0 = Color::BLACK;
+            }
+            Interaction::Hovered => {
+                border_color.0 = Color::GRAY;
+            }
+            Interaction::None => {
+                border_color.0 = BorderColor::default().0;
+            }
+        }
+    }
+
+    if !tool_state.is_changed() {
+        return;
+    }
+
+    background_color_query
+        .iter_mut()
+        .for_each(|(mut background_color, brush_mode)| {
+            if *brush_mode == tool_state.brush_mode {
+                background_color.0 = Color::SILVER;
+            } else {
+                background_color.0 = BackgroundColor::default().0;
+            }
+        });
+
+    for mut style in replace_material_row_query.iter_mut() {
+        style.display = if tool_state.brush_mode == BrushMode::Replace {
+            Display::Flex
+        } else {
+            Display::None
+        };
+    }
+}
+
+fn brush_material_options_system(
+    replace_button_query: Query<(&Interaction, &ReplaceMaterialButton), Changed<Interaction>>,
+    protect_button_query: Query<(&Interaction, &ProtectMaterialButton), Changed<Interaction>>,
+    mut replace_border_query: Query<
+        (&mut BorderColor, &ReplaceMaterialButton),
+        Without<ProtectMaterialButton>,
+    >,
+    mut protect_border_query: Query<(&mut BorderColor, &ProtectMaterialButton)>,
+    material_colors: Res<MaterialColor>,
+    mut tool_state: ResMut<ToolState>,
+) {
+    for (interaction, button) in replace_button_query.iter() {
+        if *interaction == Interaction::Pressed {
+            tool_state.replace_material = button.0;
+        }
+    }
+    for (interaction, button) in protect_button_query.iter() {
+        if *interaction == Interaction::Pressed {
+            tool_state.protected_materials[button.0] = !tool_state.protected_materials[button.0];
+        }
+    }
+
+    if !tool_state.is_changed() {
+        return;
+    }
+
+    let swatch_border = |selected: bool, material: Material| {
+        if selected {
+            Color::BLACK
+        } else {
+            material_colors.0[material]
+        }
+    };
+    for (mut border_color, button) in replace_border_query.iter_mut() {
+        border_color.0 = swatch_border(tool_state.replace_material == button.0, button.0);
+    }
+    for (mut border_color, button) in protect_border_query.iter_mut() {
+        border_color.0 = swatch_border(tool_state.protected_materials[button.0], button.0);
+    }
+}
+
 fn material_button_system(
     interaction_query: Query<(&Interaction, &MaterialButton), Changed<Interaction>>,
+    mut border_query: Query<(&mut BorderColor, &MaterialButton)>,
+    material_colors: Res<MaterialColor>,
     mut tool_state: ResMut<ToolState>,
 ) {
     for (interaction, material_button) in interaction_query.iter() {
@@ -288,6 +546,33 @@ fn material_button_system(
             tool_state.draw_type = material_button.0;
         }
     }
+
+    if !tool_state.is_changed() {
+        return;
+    }
+
+    for (mut border_color, material_button) in border_query.iter_mut() {
+        border_color.0 = if material_button.0 == tool_state.draw_type {
+            Color::BLACK
+        } else {
+            material_button_border_color(material_colors.0[material_button.0])
+        };
+    }
+}
+
+fn pick_material(
+    action_input: ActionInput,
+    cursor_tile_position: Res<CursorTilePosition>,
+    grid: FallingSandGridQuery,
+    mut tool_state: ResMut<ToolState>,
+) {
+    if !action_input.just_pressed(Action::PickMaterial) {
+        return;
+    }
+
+    if let Some(particle) = grid.get_particle(cursor_tile_position.0) {
+        tool_state.draw_type = particle.material();
+    }
 }
 
 #[derive(Default, Component, Clone, Copy, PartialEq, Eq)]
@@ -295,42 +580,112 @@ pub enum BrushShape {
     #[default]
     Rectangle,
     Circle,
+    /// Circle of which only a random fraction is filled every time it's drawn.
+    Spray,
+}
+
+/// Random number generator for drawing, seeded so sprayed strokes are replayable.
+#[derive(Resource)]
+pub struct DrawToolRng(pub StdRng);
+
+/// Which particles a stroke is allowed to overwrite.
+#[derive(Default, Component, Clone, Copy, PartialEq, Eq)]
+pub enum BrushMode {
+    #[default]
+    All,
+    AirOnly,
+    /// Only overwrite `ToolState::replace_material`.
+    Replace,
+}
+
+#[derive(Default, Component, Clone, Copy, PartialEq, Eq)]
+pub enum Tool {
+    #[default]
+    Brush,
+    Fill,
+    Line,
+    Rectangle,
+    Ellipse,
+    Select,
+    Paste,
+    Emitter,
+    Sink,
+}
+
+impl Tool {
+    /// Whether the tool draws a shape defined by dragging from one corner to another.
+    pub fn is_shape(&self) -> bool {
+        matches!(self, Tool::Line | Tool::Rectangle | Tool::Ellipse)
+    }
 }
 
 #[derive(Resource)]
 pub struct ToolState {
+    pub tool: Tool,
     pub draw_type: Material,
     pub brush_size: u32,
     pub brush_shape: BrushShape,
+    /// Fraction of the brush footprint filled by the spray brush.
+    pub spray_density: f32,
+    /// Chance of each tile of a newly placed emitter to spawn a particle every tick.
+    pub emitter_rate: f32,
+    /// Maximum number of tiles a single fill may cover, larger regions aren't filled at all.
+    pub fill_limit: usize,
+    /// Whether rectangles and ellipses are filled, or only drawn as an outline.
+    pub shape_filled: bool,
+    pub brush_mode: BrushMode,
+    pub replace_material: Material,
+    /// Materials that are never overwritten by drawing, regardless of the brush mode.
+    pub protected_materials: EnumMap<Material, bool>,
 }
 
 impl Default for ToolState {
     fn default() -> Self {
         Self {
+            tool: Tool::Brush,
             draw_type: Material::Sand,
             brush_size: 1,
             brush_shape: BrushShape::Rectangle,
+            spray_density: 0.25,
+            emitter_rate: 0.25,
+            fill_limit: 1 << 16,
+            shape_filled: true,
+            brush_mode: BrushMode::All,
+            replace_material: Material::Water,
+            protected_materials: EnumMap::default(),
         }
     }
 }
 
-fn switch_tool_system(
-    mut tool_state: ResMut<ToolState>,
-    keyboard_input: Res<ButtonInput<KeyCode>>,
-) {
-    let material_keys = HashMap::from_iter([
-        (KeyCode::Digit1, Material::Sand),
-        (KeyCode::Digit2, Material::Water),
-        (KeyCode::Digit3, Material::Fire),
-        (KeyCode::Digit4, Material::Wood),
-        (KeyCode::Digit5, Material::Bedrock),
-        (KeyCode::Digit6, Material::Oil),
-    ]);
-    if let Some(material) = keyboard_input
-        .get_pressed()
-        .find_map(|p| material_keys.get(p))
+impl ToolState {
+    /// Whether drawing may replace a particle of `material`.
+    pub fn can_paint_over(&self, material: Material) -> bool {
+        if self.protected_materials[material] {
+            return false;
+        }
+
+        match self.brush_mode {
+            BrushMode::All => true,
+            BrushMode::AirOnly => material == Material::Air,
+            BrushMode::Replace => material == self.replace_material,
+        }
+    }
+}
+
+fn switch_tool_system(mut tool_state: ResMut<ToolState>, action_input: ActionInput) {
+    if let Some(material) = MaterialIterator::new()
+        .find(|material| action_input.pressed(Action::SelectMaterial(*material)))
     {
-        tool_state.draw_type = *material;
+        tool_state.draw_type = material;
+    }
+
+    let material_count = Material::LENGTH;
+    let material_index = tool_state.draw_type.into_usize();
+    if action_input.just_pressed(Action::NextMaterial) {
+        tool_state.draw_type = Material::from_usize((material_index + 1) % material_count);
+    } else if action_input.just_pressed(Action::PreviousMaterial) {
+        tool_state.draw_type =
+            Material::from_usize((material_index + material_count - 1) % material_count);
     }
 }
 
@@ -345,25 +700,83 @@ impl Default for DrawTimer {
 fn brush_size_system(
     mut tool_state: ResMut<ToolState>,
     mut mouse_wheel_events: EventReader<MouseWheel>,
-    keyboard_input: Res<ButtonInput<KeyCode>>,
+    action_input: ActionInput,
     mut brush_size_text_query: Query<&mut Text, With<BrushSizeText>>,
 ) {
-    if !keyboard_input.pressed(KeyCode::ControlLeft) || mouse_wheel_events.is_empty() {
+    let mut size_change = 0;
+    if action_input.pressed(Action::BrushSizeModifier) {
+        for event in mouse_wheel_events.read() {
+            size_change += event.y.signum() as i32;
+        }
+    }
+    if action_input.just_pressed(Action::IncreaseBrushSize) {
+        size_change += 1;
+    }
+    if action_input.just_pressed(Action::DecreaseBrushSize) {
+        size_change -= 1;
+    }
+    if size_change == 0 {
+        return;
+    }
+
+    tool_state.brush_size = tool_state
+        .brush_size
+        .saturating_add_signed(size_change)
+        .max(1);
+    brush_size_text_query.single_mut().sections[1].value = tool_state.brush_size.to_string();
+}
+
+const SPRAY_DENSITY_STEP: f32 = 0.05;
+
+/// Adjusts the spray density, or the emitter rate while the emitter tool is selected.
+fn spray_density_system(
+    mut tool_state: ResMut<ToolState>,
+    mut mouse_wheel_events: EventReader<MouseWheel>,
+    action_input: ActionInput,
+    mut spray_density_text_query: Query<
+        &mut Text,
+        (With<SprayDensityText>, Without<EmitterRateText>),
+    >,
+    mut emitter_rate_text_query: Query<&mut Text, With<EmitterRateText>>,
+) {
+    if !action_input.pressed(Action::SprayDensityModifier) || mouse_wheel_events.is_empty() {
         return;
     }
 
-    let mut text = brush_size_text_query.single_mut();
+    let emitter = tool_state.tool == Tool::Emitter;
+    let (value, mut text, label): (_, _, fn(f32) -> String) = if emitter {
+        (
+            &mut tool_state.emitter_rate,
+            emitter_rate_text_query.single_mut(),
+            emitter_rate_label,
+        )
+    } else {
+        (
+            &mut tool_state.spray_density,
+            spray_density_text_query.single_mut(),
+            percent_label,
+        )
+    };
     for event in mouse_wheel_events.read() {
         if event.y > 0. {
-            tool_state.brush_size += 1;
+            *value = (*value + SPRAY_DENSITY_STEP).min(1.);
         } else if event.y < 0. {
-            tool_state.brush_size = tool_state.brush_size.saturating_sub(1).max(1);
+            *value = (*value - SPRAY_DENSITY_STEP).max(SPRAY_DENSITY_STEP);
         }
 
-        text.sections[1].value = tool_state.brush_size.to_string();
+        text.sections[1].value = label(*value);
     }
 }
 
+fn percent_label(fraction: f32) -> String {
+    format!("{:.0}%", fraction * 100.)
+}
+
+/// Chance that an emitter fills each of its empty tiles in a tick.
+fn emitter_rate_label(emitter_rate: f32) -> String {
+    format!("{} per tick", percent_label(emitter_rate))
+}
+
 fn brush_shape_picker_system(
     mut brush_shape_button_query: Query<
         (&Interaction, &BrushShape, &mut BorderColor),
@@ -398,22 +811,59 @@ fn brush_shape_picker_system(
         });
 }
 
+fn tool_picker_system(
+    mut tool_button_query: Query<(&Interaction, &Tool, &mut BorderColor), Changed<Interaction>>,
+    mut background_color_query: Query<(&mut BackgroundColor, &Tool)>,
+    mut tool_state: ResMut<ToolState>,
+) {
+    for (interaction, tool, mut border_color) in tool_button_query.iter_mut() {
+        match *interaction {
+            Interaction::Pressed => {
+                tool_state.tool = *tool;
+                border_color.0 = Color::BLACK;
+            }
+            Interaction::Hovered => {
+                border_color.0 = Color::GRAY;
+            }
+            Interaction::None => {
+                border_color.0 = BorderColor::default().0;
+            }
+        }
+    }
+
+    background_color_query
+        .iter_mut()
+        .for_each(|(mut background_color, tool)| {
+            if *tool == tool_state.tool {
+                background_color.0 = Color::SILVER;
+            } else {
+                background_color.0 = BackgroundColor::default().0;
+            }
+        });
+}
+
 #[derive(Default)]
 struct LastDrawPosition(Option<IVec2>);
 
 #[derive(Component, Debug, Reflect)]
-struct Stroke(Vec<IVec2>);
+struct Stroke {
+    points: Vec<IVec2>,
+    material: Material,
+}
 
+#[allow(clippy::too_many_arguments)]
 fn calculate_stroke(
     mut commands: Commands,
-    mouse_button_input: Res<ButtonInput<MouseButton>>,
+    action_input: ActionInput,
     cursor_tile_position: Res<CursorTilePosition>,
     mut timer: Local<DrawTimer>,
     time: Res<Time>,
     mut last_draw_position: Local<LastDrawPosition>,
     tool_state: Res<ToolState>,
+    mut rng: ResMut<DrawToolRng>,
 ) {
-    if !mouse_button_input.pressed(MouseButton::Left) {
+    let erasing = action_input.pressed(Action::Erase) && !action_input.pressed(Action::Draw);
+    if tool_state.tool != Tool::Brush || !(erasing || action_input.pressed(Action::Draw)) {
         last_draw_position.0 = None;
         return;
     }
@@ -433,39 +883,336 @@ fn calculate_stroke(
 
         let mut stroke_points = Vec::new();
         for point in line.iter() {
-            match tool_state.brush_shape {
-                BrushShape::Rectangle => {
-                    for dx in 0..tool_state.brush_size {
-                        for dy in 0..tool_state.brush_size {
-                            let adjusted_point = IVec2::new(
-                                point.x + dx as i32 - (tool_state.brush_size / 2) as i32,
-                                point.y + dy as i32 - (tool_state.brush_size / 2) as i32,
-                            );
-                            stroke_points.push(adjusted_point);
-                        }
-                    }
+            stroke_points.extend(brush_points(
+                *point,
+                tool_state.brush_shape,
+                tool_state.brush_size,
+            ));
+        }
+
+        if tool_state.brush_shape == BrushShape::Spray {
+            stroke_points = spray_points(stroke_points, tool_state.spray_density, &mut rng.0);
+        }
+
+        commands.spawn(Stroke {
+            points: stroke_points,
+            material: if erasing {
+                Material::Air
+            } else {
+                tool_state.draw_type
+            },
+        });
+        last_draw_position.0 = Some(current_tile_pos);
+    }
+}
+
+/// Tiles covered by the brush when centered on `point`.
+pub fn brush_points(point: IVec2, brush_shape: BrushShape, brush_size: u32) -> Vec<IVec2> {
+    let mut points = Vec::new();
+    match brush_shape {
+        BrushShape::Rectangle => {
+            for dx in 0..brush_size {
+                for dy in 0..brush_size {
+                    let adjusted_point = IVec2::new(
+                        point.x + dx as i32 - (brush_size / 2) as i32,
+                        point.y + dy as i32 - (brush_size / 2) as i32,
+                    );
+                    points.push(adjusted_point);
                 }
-                BrushShape::Circle => {
-                    let radius = tool_state.brush_size as i32 / 2;
-                    for dx in -radius..=radius {
-                        for dy in -radius..=radius {
-                            if dx.pow(2) + dy.pow(2) <= radius.pow(2) {
-                                let adjusted_point = IVec2::new(point.x + dx, point.y + dy);
-                                stroke_points.push(adjusted_point);
-                            }
-                        }
+            }
+        }
+        BrushShape::Circle | BrushShape::Spray => {
+            let radius = brush_size as i32 / 2;
+            for dx in -radius..=radius {
+                for dy in -radius..=radius {
+                    if dx.pow(2) + dy.pow(2) <= radius.pow(2) {
+                        let adjusted_point = IVec2::new(point.x + dx, point.y + dy);
+                        points.push(adjusted_point);
                     }
                 }
             }
         }
+    }
+    points
+}
 
-        commands.spawn(Stroke(stroke_points));
-        last_draw_position.0 = Some(current_tile_pos);
+/// Corners of the tiles covered by a rectangle brush centered on `point`.
+fn brush_rectangle_bounds(point: IVec2, brush_size: u32) -> (IVec2, IVec2) {
+    let min = point - IVec2::splat((brush_size / 2) as i32);
+    (min, min + IVec2::splat(brush_size as i32 - 1))
+}
+
+/// Outline of the brush footprint under the cursor, for the tools that draw with the brush.
+fn draw_brush_preview(
+    mut gizmos: Gizmos,
+    tool_state: Res<ToolState>,
+    cursor_tile_position: Res<CursorTilePosition>,
+    falling_sand_settings: Res<FallingSandSettings>,
+) {
+    if !matches!(
+        tool_state.tool,
+        Tool::Brush | Tool::Line | Tool::Emitter | Tool::Sink
+    ) {
+        return;
+    }
+
+    let preview_color = Color::ORANGE_RED;
+    match tool_state.brush_shape {
+        BrushShape::Rectangle => {
+            let (min, max) = brush_rectangle_bounds(cursor_tile_position.0, tool_state.brush_size);
+            let (center, size) = tile_rect_world_bounds(&falling_sand_settings, min, max);
+            gizmos.rect_2d(center, 0., size, preview_color);
+        }
+        BrushShape::Circle | BrushShape::Spray => {
+            let grid_size = IVec2::new(
+                falling_sand_settings.size.0 as i32,
+                falling_sand_settings.size.1 as i32,
+            );
+            let tile_size = falling_sand_settings.tile_size;
+            let center = get_world_position_of_tile(cursor_tile_position.0, grid_size, tile_size);
+            let radius = (tool_state.brush_size / 2) as f32 + 0.5;
+            gizmos.circle_2d(center, radius * tile_size as f32, preview_color);
+        }
+    }
+}
+
+/// Drops a shape drag once `Draw` is released, including over the UI where `calculate_shape`
+/// doesn't run, so the next click doesn't commit a shape from the old start.
+fn cancel_shape_drag(action_input: ActionInput, mut drag_start: ResMut<ShapeDragStart>) {
+    if drag_start.0.is_some() && !action_input.pressed(Action::Draw) {
+        drag_start.0 = None;
+    }
+}
+
+/// Keep a random `density` fraction of the brush footprint. Overlapping footprints along a stroke
+/// are merged first, so the density doesn't depend on how fast the cursor moves.
+fn spray_points(points: Vec<IVec2>, density: f32, rng: &mut StdRng) -> Vec<IVec2> {
+    points
+        .into_iter()
+        .unique()
+        .filter(|_| rng.gen_bool(density as f64))
+        .collect()
+}
+
+#[derive(Resource, Default)]
+struct ShapeDragStart(Option<IVec2>);
+
+fn calculate_shape(
+    mut commands: Commands,
+    action_input: ActionInput,
+    cursor_tile_position: Res<CursorTilePosition>,
+    mut drag_start: ResMut<ShapeDragStart>,
+    tool_state: Res<ToolState>,
+    falling_sand_settings: Res<FallingSandSettings>,
+    mut gizmos: Gizmos,
+) {
+    if !tool_state.tool.is_shape() {
+        drag_start.0 = None;
+        return;
+    }
+
+    if action_input.just_pressed(Action::Draw) {
+        drag_start.0 = Some(cursor_tile_position.0);
+    }
+
+    let Some(start_pos) = drag_start.0 else {
+        return;
+    };
+    let end_pos = cursor_tile_position.0;
+
+    if !action_input.pressed(Action::Draw) {
+        commands.spawn(Stroke {
+            points: shape_points(&tool_state, start_pos, end_pos),
+            material: tool_state.draw_type,
+        });
+        drag_start.0 = None;
+        return;
+    }
+
+    let grid_size = IVec2::new(
+        falling_sand_settings.size.0 as i32,
+        falling_sand_settings.size.1 as i32,
+    );
+    let tile_size = falling_sand_settings.tile_size;
+    let start = get_world_position_of_tile(start_pos.min(end_pos), grid_size, tile_size);
+    let end = get_world_position_of_tile(start_pos.max(end_pos), grid_size, tile_size);
+    let half_tile = Vec2::splat(tile_size as f32 / 2.);
+    let preview_color = Color::ORANGE_RED;
+
+    match tool_state.tool {
+        Tool::Line => {
+            let line_start = get_world_position_of_tile(start_pos, grid_size, tile_size);
+            let line_end = get_world_position_of_tile(end_pos, grid_size, tile_size);
+            gizmos.line_2d(line_start, line_end, preview_color);
+        }
+        Tool::Rectangle => {
+            gizmos.rect_2d(
+                (start + end) / 2.,
+                0.,
+                end - start + half_tile * 2.,
+                preview_color,
+            );
+        }
+        Tool::Ellipse => {
+            gizmos.ellipse_2d(
+                (start + end) / 2.,
+                0.,
+                (end - start) / 2. + half_tile,
+                preview_color,
+            );
+        }
+        Tool::Brush | Tool::Fill | Tool::Select | Tool::Paste | Tool::Emitter | Tool::Sink => {}
     }
 }
 
+fn shape_points(tool_state: &ToolState, start: IVec2, end: IVec2) -> Vec<IVec2> {
+    match tool_state.tool {
+        Tool::Line => {
+            let line = Bresenham::new(start.into(), end.into()).map(Into::<IVec2>::into);
+            line.flat_map(|point| {
+                brush_points(point, tool_state.brush_shape, tool_state.brush_size)
+            })
+            .unique()
+            .collect()
+        }
+        Tool::Rectangle => rectangle_points(
+            start,
+            end,
+            tool_state.shape_filled,
+            tool_state.brush_size as i32,
+        ),
+        Tool::Ellipse => ellipse_points(
+            start,
+            end,
+            tool_state.shape_filled,
+            tool_state.brush_size as i32,
+        ),
+        Tool::Brush | Tool::Fill | Tool::Select | Tool::Paste | Tool::Emitter | Tool::Sink => {
+            Vec::new()
+        }
+    }
+}
+
+/// Tiles of the rectangle spanned by the corners `a` and `b`. Outlines are `thickness` tiles
+/// thick, measured inwards.
+fn rectangle_points(a: IVec2, b: IVec2, filled: bool, thickness: i32) -> Vec<IVec2> {
+    let min = a.min(b);
+    let max = a.max(b);
+    (min.x..=max.x)
+        .cartesian_product(min.y..=max.y)
+        .map(IVec2::from)
+        .filter(|point| {
+            filled
+                || point.x - min.x < thickness
+                || max.x - point.x < thickness
+                || point.y - min.y < thickness
+                || max.y - point.y < thickness
+        })
+        .collect()
+}
+
+/// Tiles of the ellipse inscribed in the rectangle spanned by the corners `a` and `b`. Outlines
+/// are `thickness` tiles thick, measured inwards.
+fn ellipse_points(a: IVec2, b: IVec2, filled: bool, thickness: i32) -> Vec<IVec2> {
+    let min = a.min(b);
+    let max = a.max(b);
+    let center = (min.as_vec2() + max.as_vec2() + Vec2::ONE) / 2.;
+    let radii = (max - min + IVec2::ONE).as_vec2() / 2.;
+    let inner_radii = radii - Vec2::splat(thickness as f32);
+
+    let is_inside = |point: Vec2, radii: Vec2| {
+        let normalized = (point - center) / radii;
+        normalized.length_squared() <= 1.
+    };
+
+    (min.x..=max.x)
+        .cartesian_product(min.y..=max.y)
+        .map(IVec2::from)
+        .filter(|point| {
+            // Test the center of the tile
+            let point = point.as_vec2() + Vec2::splat(0.5);
+            is_inside(point, radii)
+                && (filled
+                    || inner_radii.x <= 0.
+                    || inner_radii.y <= 0.
+                    || !is_inside(point, inner_radii))
+        })
+        .collect()
+}
+
+fn calculate_fill(
+    mut commands: Commands,
+    action_input: ActionInput,
+    cursor_tile_position: Res<CursorTilePosition>,
+    tool_state: Res<ToolState>,
+    grid: FallingSandGridQuery,
+) {
+    if tool_state.tool != Tool::Fill || !action_input.just_pressed(Action::Draw) {
+        return;
+    }
+
+    let start_pos = cursor_tile_position.0;
+    let Some(target_material) = grid.get_particle(start_pos).map(|p| p.material()) else {
+        return;
+    };
+    if target_material == tool_state.draw_type {
+        return;
+    }
+
+    let is_fillable = |pos: IVec2| {
+        grid.get_particle(pos)
+            .is_some_and(|particle| particle.material() == target_material)
+    };
+    match flood_fill(start_pos, tool_state.fill_limit, is_fillable) {
+        Some(fill_points) => {
+            commands.spawn(Stroke {
+                points: fill_points,
+                material: tool_state.draw_type,
+            });
+        }
+        None => {
+            warn!(
+                "Fill region is larger than the fill limit of {} tiles",
+                tool_state.fill_limit
+            );
+        }
+    }
+}
+
+/// Collect the 4-connected region of fillable tiles around `start`, or `None` if the region is
+/// larger than `limit` tiles.
+fn flood_fill(
+    start: IVec2,
+    limit: usize,
+    is_fillable: impl Fn(IVec2) -> bool,
+) -> Option<Vec<IVec2>> {
+    if !is_fillable(start) {
+        return Some(Vec::new());
+    }
+
+    let mut visited = HashSet::from_iter([start]);
+    let mut queue = VecDeque::from([start]);
+    let mut region = Vec::new();
+
+    while let Some(pos) = queue.pop_front() {
+        region.push(pos);
+        if region.len() > limit {
+            return None;
+        }
+
+        for neighbor in [left(pos), right(pos), below(pos), above(pos)] {
+            if !visited.contains(&neighbor) && is_fillable(neighbor) {
+                visited.insert(neighbor);
+                queue.push_back(neighbor);
+            }
+        }
+    }
+
+    Some(region)
+}
+
 #[derive(Resource, Default, Reflect)]
-struct CursorTilePosition(pub IVec2);
+pub struct CursorTilePosition(pub IVec2);
 
 fn cursor_tile_position_system(
     cursor_world_position: Res<CursorWorldPosition>,
@@ -486,25 +1233,40 @@ fn cursor_tile_position_system(
     }
 }
 
-fn get_tile_at_world_position(world_position: Vec2, grid_size: IVec2, tile_size: u32) -> IVec2 {
+pub fn get_tile_at_world_position(world_position: Vec2, grid_size: IVec2, tile_size: u32) -> IVec2 {
     let x = (world_position.x / tile_size as f32 + grid_size.x as f32 / 2.0) as i32;
     let y = (world_position.y / tile_size as f32 + grid_size.y as f32 / 2.0) as i32;
     IVec2::new(x, y)
 }
 
+/// World position of the center of a tile, the inverse of `get_tile_at_world_position`.
+pub fn get_world_position_of_tile(tile_position: IVec2, grid_size: IVec2, tile_size: u32) -> Vec2 {
+    (tile_position.as_vec2() + Vec2::splat(0.5) - grid_size.as_vec2() / 2.0) * tile_size as f32
+}
+
+/// World space center and size of the tile rectangle from `min` to `max`, inclusive.
+pub fn tile_rect_world_bounds(
+    falling_sand_settings: &FallingSandSettings,
+    min: IVec2,
+    max: IVec2,
+) -> (Vec2, Vec2) {
+    let grid_size = IVec2::new(
+        falling_sand_settings.size.0 as i32,
+        falling_sand_settings.size.1 as i32,
+    );
+    let tile_size = falling_sand_settings.tile_size;
+    let half_tile = Vec2::splat(tile_size as f32 / 2.);
+    let min = get_world_position_of_tile(min, grid_size, tile_size) - half_tile;
+    let max = get_world_position_of_tile(max, grid_size, tile_size) + half_tile;
+    ((min + max) / 2., max - min)
+}
+
 fn spawn_chunk_under_stroke(
     mut chunk_creation_params: ChunkCreationParams,
     stroke_query: Query<&Stroke>,
 ) {
     for stroke in stroke_query.iter() {
-        let unspawned_stroke_chunk_positions = stroke
-            .0
-            .iter()
-            .map(|pos| tile_pos_to_chunk_pos(*pos))
-            .unique()
-            .filter(|pos| !chunk_creation_params.chunk_positions.contains(*pos))
-            .collect_vec();
-        chunk_creation_params.spawn_chunks(unspawned_stroke_chunk_positions);
+        chunk_creation_params.spawn_chunks_under(stroke.points.iter().copied());
     }
 }
 
@@ -515,8 +1277,13 @@ fn draw_particles(
     mut commands: Commands,
 ) {
     stroke_query.iter().for_each(|(entity, stroke)| {
-        stroke.0.iter().for_each(|pos| {
-            grid.set_particle(*pos, tool_state.draw_type);
+        stroke.points.iter().for_each(|pos| {
+            let can_paint = grid
+                .get_particle(*pos)
+                .is_some_and(|particle| tool_state.can_paint_over(particle.material()));
+            if can_paint {
+                grid.set_particle(*pos, stroke.material);
+            }
         });
 
         commands.entity(entity).despawn();
@@ -544,4 +1311,100 @@ mod test {
         let tile_position = get_tile_at_world_position(cursor_position, grid_size, tile_size);
         assert_eq!(tile_position, IVec2::new(0, 9));
     }
+
+    #[test]
+    fn test_brush_rectangle_bounds() {
+        for brush_size in 1..6 {
+            let point = IVec2::new(3, -2);
+            let points = brush_points(point, BrushShape::Rectangle, brush_size);
+            let min = points.iter().copied().reduce(IVec2::min).unwrap();
+            let max = points.iter().copied().reduce(IVec2::max).unwrap();
+            assert_eq!(brush_rectangle_bounds(point, brush_size), (min, max));
+        }
+    }
+
+    #[test]
+    fn test_flood_fill() {
+        // A 4x4 box with walls at x = 0, x = 3, y = 0 and y = 3
+        let is_inside_box = |pos: IVec2| pos.x > 0 && pos.x < 3 && pos.y > 0 && pos.y < 3;
+
+        let mut region = flood_fill(IVec2::new(1, 1), 16, is_inside_box).unwrap();
+        region.sort_by_key(|pos| (pos.x, pos.y));
+        assert_eq!(
+            region,
+            vec![
+                IVec2::new(1, 1),
+                IVec2::new(1, 2),
+                IVec2::new(2, 1),
+                IVec2::new(2, 2)
+            ]
+        );
+
+        assert_eq!(flood_fill(IVec2::new(1, 1), 3, is_inside_box), None);
+        assert_eq!(
+            flood_fill(IVec2::new(0, 0), 16, is_inside_box),
+            Some(vec![])
+        );
+    }
+
+    #[test]
+    fn test_can_paint_over() {
+        let mut tool_state = ToolState::default();
+        assert!(tool_state.can_paint_over(Material::Bedrock));
+
+        tool_state.protected_materials[Material::Bedrock] = true;
+        assert!(!tool_state.can_paint_over(Material::Bedrock));
+        assert!(tool_state.can_paint_over(Material::Sand));
+
+        tool_state.brush_mode = BrushMode::AirOnly;
+        assert!(tool_state.can_paint_over(Material::Air));
+        assert!(!tool_state.can_paint_over(Material::Sand));
+
+        tool_state.brush_mode = BrushMode::Replace;
+        tool_state.replace_material = Material::Sand;
+        assert!(tool_state.can_paint_over(Material::Sand));
+        assert!(!tool_state.can_paint_over(Material::Water));
+    }
+
+    #[test]
+    fn test_spray_points() {
+        let points = brush_points(IVec2::ZERO, BrushShape::Spray, 21);
+        let overlapping_points = points.iter().chain(points.iter()).copied().collect_vec();
+
+        let sprayed = spray_points(
+            overlapping_points.clone(),
+            0.5,
+            &mut StdRng::seed_from_u64(0),
+        );
+        assert!(sprayed.len() > points.len() / 4);
+        assert!(sprayed.len() < points.len() * 3 / 4);
+        assert!(sprayed.iter().all_unique());
+
+        let replayed = spray_points(overlapping_points, 0.5, &mut StdRng::seed_from_u64(0));
+        assert_eq!(sprayed, replayed);
+    }
+
+    #[test]
+    fn test_rectangle_points() {
+        let filled = rectangle_points(IVec2::new(3, 3), IVec2::new(0, 0), true, 1);
+        assert_eq!(filled.len(), 16);
+
+        let outline = rectangle_points(IVec2::new(0, 0), IVec2::new(3, 3), false, 1);
+        assert_eq!(outline.len(), 12);
+        assert!(!outline.contains(&IVec2::new(1, 1)));
+        assert!(outline.contains(&IVec2::new(3, 0)));
+    }
+
+    #[test]
+    fn test_ellipse_points() {
+        let filled = ellipse_points(IVec2::new(0, 0), IVec2::new(6, 6), true, 1);
+        assert!(filled.contains(&IVec2::new(3, 3)));
+        assert!(filled.contains(&IVec2::new(0, 3)));
+        assert!(!filled.contains(&IVec2::new(0, 0)));
+
+        let outline = ellipse_points(IVec2::new(0, 0), IVec2::new(6, 6), false, 1);
+        assert!(!outline.contains(&IVec2::new(3, 3)));
+        assert!(outline.contains(&IVec2::new(0, 3)));
+        assert!(outline.len() < filled.len());
+    }
 }
@@ -0,0 +1,268 @@
+use bevy::{
+    app::{App, FixedUpdate, Plugin, Update},
+    ecs::{
+        component::Component,
+        entity::Entity,
+        query::Added,
+        schedule::{
+            apply_deferred,
+            common_conditions::{not, resource_exists},
+            IntoSystemConfigs,
+        },
+        system::{Commands, Query, Res, ResMut},
+    },
+    gizmos::gizmos::Gizmos,
+    math::IVec2,
+    render::color::Color,
+};
+use itertools::Itertools;
+use rand::Rng;
+
+use crate::{
+    draw_tool::{brush_points, tile_rect_world_bounds, CursorTilePosition, Tool, ToolState},
+    falling_sand::{ChunkCreationParams, FallingSandRng, FallingSandSet, FallingSandSettings},
+    falling_sand_grid::FallingSandGridQuery,
+    hovering_ui::UiFocused,
+    input_map::{Action, ActionInput},
+    material::{Material, MaterialColor},
+    util::{above, below, left, right, tile_pos_to_chunk_pos},
+};
+
+pub struct EmitterPlugin;
+
+impl Plugin for EmitterPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (
+                (place_emitters, remove_emitters).run_if(not(resource_exists::<UiFocused>)),
+                apply_deferred,
+                spawn_chunks_under_emitters,
+                apply_deferred,
+                draw_emitter_gizmos,
+            )
+                .chain(),
+        )
+        .add_systems(
+            FixedUpdate,
+            (run_emitters, run_sinks).chain().before(FallingSandSet),
+        );
+    }
+}
+
+/// Spawns `material` into the air tiles it covers. Each covered tile is filled with a chance of
+/// `rate` every tick.
+#[derive(Component)]
+pub struct Emitter {
+    pub material: Material,
+    pub rate: f32,
+    pub tiles: Vec<IVec2>,
+}
+
+/// Removes every particle that touches it: the particles in the tiles it covers and in the tiles
+/// next to them.
+#[derive(Component)]
+pub struct Sink {
+    pub tiles: Vec<IVec2>,
+    /// `tiles` and the tiles next to them.
+    reach: Vec<IVec2>,
+}
+
+impl Sink {
+    pub fn new(tiles: Vec<IVec2>) -> Self {
+        let reach = tiles
+            .iter()
+            .flat_map(|&tile| [tile, above(tile), below(tile), left(tile), right(tile)])
+            .unique()
+            .collect();
+        Sink { tiles, reach }
+    }
+}
+
+/// Whether a sink removes particles of `material`. Bedrock isn't removed, so sinks placed
+/// against a wall don't eat into it.
+fn sink_removes(material: Material) -> bool {
+    !matches!(material, Material::Air | Material::Bedrock)
+}
+
+fn place_emitters(
+    mut commands: Commands,
+    action_input: ActionInput,
+    cursor_tile_position: Res<CursorTilePosition>,
+    tool_state: Res<ToolState>,
+) {
+    if !action_input.just_pressed(Action::Draw) {
+        return;
+    }
+
+    let tiles = brush_points(
+        cursor_tile_position.0,
+        tool_state.brush_shape,
+        tool_state.brush_size,
+    );
+    match tool_state.tool {
+        Tool::Emitter => {
+            commands.spawn(Emitter {
+                material: tool_state.draw_type,
+                rate: tool_state.emitter_rate,
+                tiles,
+            });
+        }
+        Tool::Sink => {
+            commands.spawn(Sink::new(tiles));
+        }
+        _ => {}
+    }
+}
+
+fn remove_emitters(
+    mut commands: Commands,
+    action_input: ActionInput,
+    cursor_tile_position: Res<CursorTilePosition>,
+    tool_state: Res<ToolState>,
+    emitter_query: Query<(Entity, &Emitter)>,
+    sink_query: Query<(Entity, &Sink)>,
+) {
+    if !matches!(tool_state.tool, Tool::Emitter | Tool::Sink)
+        || !action_input.just_pressed(Action::Remove)
+    {
+        return;
+    }
+
+    let emitters = emitter_query.iter().map(|(entity, e)| (entity, &e.tiles));
+    let sinks = sink_query.iter().map(|(entity, s)| (entity, &s.tiles));
+    for (entity, tiles) in emitters.chain(sinks) {
+        if tiles.contains(&cursor_tile_position.0) {
+            commands.entity(entity).despawn();
+        }
+    }
+}
+
+fn spawn_chunks_under_emitters(
+    mut chunk_creation_params: ChunkCreationParams,
+    emitter_query: Query<&Emitter, Added<Emitter>>,
+    sink_query: Query<&Sink, Added<Sink>>,
+) {
+    let emitter_tiles = emitter_query.iter().flat_map(|e| e.tiles.iter().copied());
+    let sink_tiles = sink_query.iter().flat_map(|s| s.reach.iter().copied());
+    chunk_creation_params.spawn_chunks_under(emitter_tiles.chain(sink_tiles));
+}
+
+/// One tile out of `tiles` for every chunk they cover.
+fn one_tile_per_chunk(tiles: &[IVec2]) -> impl Iterator<Item = IVec2> + '_ {
+    tiles
+        .iter()
+        .copied()
+        .unique_by(|&tile| tile_pos_to_chunk_pos(tile))
+}
+
+fn run_emitters(
+    mut grid: FallingSandGridQuery,
+    emitter_query: Query<&Emitter>,
+    mut rng: ResMut<FallingSandRng>,
+) {
+    for emitter in emitter_query.iter() {
+        for &tile in &emitter.tiles {
+            let is_air = grid
+                .get_particle(tile)
+                .is_some_and(|particle| particle.material() == Material::Air);
+            if is_air && rng.0.gen_bool(emitter.rate as f64) {
+                grid.set_particle(tile, emitter.material);
+            }
+        }
+
+        // A source that is buried in its own material doesn't change anything, but its chunk
+        // must stay active so it can keep emitting once there's room again.
+        for tile in one_tile_per_chunk(&emitter.tiles) {
+            grid.wake_chunk(tile);
+        }
+    }
+}
+
+fn run_sinks(mut grid: FallingSandGridQuery, sink_query: Query<&Sink>) {
+    for sink in sink_query.iter() {
+        for &tile in &sink.reach {
+            let can_remove = grid
+                .get_particle(tile)
+                .is_some_and(|particle| sink_removes(particle.material()));
+            if can_remove {
+                grid.set_particle(tile, Material::Air);
+            }
+        }
+
+        for tile in one_tile_per_chunk(&sink.reach) {
+            grid.wake_chunk(tile);
+        }
+    }
+}
+
+fn draw_emitter_gizmos(
+    mut gizmos: Gizmos,
+    emitter_query: Query<&Emitter>,
+    sink_query: Query<&Sink>,
+    falling_sand_settings: Res<FallingSandSettings>,
+    material_color: Res<MaterialColor>,
+) {
+    let mut draw_bounds = |tiles: &[IVec2], color: Color| {
+        let Some(min) = tiles.iter().copied().reduce(IVec2::min) else {
+            return;
+        };
+        let max = tiles.iter().copied().reduce(IVec2::max).unwrap();
+        let (center, size) = tile_rect_world_bounds(&falling_sand_settings, min, max);
+        gizmos.rect_2d(center, 0., size, color);
+    };
+
+    for emitter in emitter_query.iter() {
+        draw_bounds(&emitter.tiles, material_color[emitter.material]);
+    }
+    for sink in sink_query.iter() {
+        draw_bounds(&sink.tiles, Color::BLACK);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_one_tile_per_chunk() {
+        let tiles = [
+            IVec2::new(-1, 0),
+            IVec2::new(0, 0),
+            IVec2::new(1, 0),
+            IVec2::new(63, 64),
+        ];
+        assert_eq!(
+            one_tile_per_chunk(&tiles).collect_vec(),
+            vec![IVec2::new(-1, 0), IVec2::new(0, 0), IVec2::new(63, 64)]
+        );
+    }
+
+    #[test]
+    fn test_sink_reaches_touching_tiles() {
+        let sink = Sink::new(vec![IVec2::new(0, 0), IVec2::new(1, 0)]);
+        let mut reach = sink.reach.clone();
+        reach.sort_by_key(|tile| (tile.x, tile.y));
+        assert_eq!(
+            reach,
+            vec![
+                IVec2::new(-1, 0),
+                IVec2::new(0, -1),
+                IVec2::new(0, 0),
+                IVec2::new(0, 1),
+                IVec2::new(1, -1),
+                IVec2::new(1, 0),
+                IVec2::new(1, 1),
+                IVec2::new(2, 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_sink_keeps_air_and_bedrock() {
+        assert!(sink_removes(Material::Water));
+        assert!(sink_removes(Material::Sand));
+        assert!(!sink_removes(Material::Air));
+        assert!(!sink_removes(Material::Bedrock));
+    }
+}
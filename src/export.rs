@@ -0,0 +1,216 @@
+use std::{
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use bevy::{
+    app::{App, Plugin, Update},
+    ecs::system::{Res, Resource},
+    log::{info, warn},
+    math::{IVec2, UVec2},
+    render::{
+        render_asset::RenderAssetUsages,
+        render_resource::{Extent3d, TextureDimension, TextureFormat},
+        texture::Image,
+    },
+    tasks::AsyncComputeTaskPool,
+};
+
+use crate::{
+    consts::CHUNK_SIZE,
+    falling_sand::ChunkPositions,
+    falling_sand_grid::FallingSandGridQuery,
+    input_map::{Action, ActionInput},
+    material::Material,
+    particle_grid::Particle,
+    render::cpu::ParticlePalette,
+    selection::Selection,
+};
+
+pub struct ExportPlugin {
+    pub settings: ExportSettings,
+}
+
+impl Plugin for ExportPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(self.settings.clone())
+            .add_systems(Update, (export_selection, export_world));
+    }
+}
+
+#[derive(Resource, Clone)]
+pub struct ExportSettings {
+    /// Width and height in pixels of a tile in the exported image.
+    pub scale: u32,
+    pub directory: PathBuf,
+}
+
+impl Default for ExportSettings {
+    fn default() -> Self {
+        Self {
+            scale: 1,
+            directory: PathBuf::from("exports"),
+        }
+    }
+}
+
+/// Value of every tile in the rectangle from `min` to `max` inclusive, upright with the row of
+/// `max.y` at the top.
+pub fn rasterize_tiles<T>(min: IVec2, max: IVec2, tile_value: impl Fn(IVec2) -> T) -> Vec<T> {
+    (min.y..=max.y)
+        .rev()
+        .flat_map(|y| (min.x..=max.x).map(move |x| IVec2::new(x, y)))
+        .map(tile_value)
+        .collect()
+}
+
+/// `pixels` of an image `width` pixels wide with every pixel drawn as a `scale` by `scale`
+/// square.
+pub fn scale_pixels<T: Copy>(pixels: &[T], width: usize, scale: u32) -> Vec<T> {
+    let scale = scale.max(1) as usize;
+    if scale == 1 {
+        return pixels.to_vec();
+    }
+    let mut scaled = Vec::with_capacity(pixels.len() * scale * scale);
+    for row in pixels.chunks(width.max(1)) {
+        let row_start = scaled.len();
+        for &pixel in row {
+            scaled.extend((0..scale).map(|_| pixel));
+        }
+        for _ in 1..scale {
+            scaled.extend_from_within(row_start..row_start + row.len() * scale);
+        }
+    }
+    scaled
+}
+
+/// Save RGBA `pixels` of an image of `size`, in rows starting at the top.
+pub fn save_png_pixels(path: &Path, size: UVec2, pixels: Vec<u8>) -> Result<(), Box<dyn Error>> {
+    let image = Image::new(
+        Extent3d {
+            width: size.x,
+            height: size.y,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        pixels,
+        TextureFormat::Rgba8UnormSrgb,
+        RenderAssetUsages::MAIN_WORLD,
+    );
+    if let Some(directory) = path.parent() {
+        fs::create_dir_all(directory)?;
+    }
+    image.try_into_dynamic()?.save(path)?;
+    Ok(())
+}
+
+/// Smallest tile rectangle that holds all spawned chunks.
+fn world_bounds(chunk_positions: &ChunkPositions) -> Option<(IVec2, IVec2)> {
+    let (min, max) = chunk_positions
+        .occupied_positions()
+        .fold((IVec2::MAX, IVec2::MIN), |(min, max), position| {
+            (min.min(position), max.max(position))
+        });
+    (min.x <= max.x).then(|| {
+        (
+            min * CHUNK_SIZE,
+            (max + IVec2::ONE) * CHUNK_SIZE - IVec2::ONE,
+        )
+    })
+}
+
+/// Export the tile rectangle from `min` to `max`. The tiles are read right away, the image is
+/// scaled, encoded and written in the background.
+fn export(
+    name: &str,
+    (min, max): (IVec2, IVec2),
+    export_settings: &ExportSettings,
+    grid: &FallingSandGridQuery,
+    palette: &ParticlePalette,
+) {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let path = export_settings
+        .directory
+        .join(format!("{}-{}.png", name, timestamp));
+    // Tiles without a chunk are exported as air
+    let air = palette.particle_color(Particle::new(Material::Air, 0));
+    let tiles = rasterize_tiles(min, max, |position| {
+        grid.get_particle(position)
+            .map_or(air, |particle| palette.particle_color(particle))
+    });
+    let tile_count = (max - min + IVec2::ONE).as_uvec2();
+    let scale = export_settings.scale.max(1);
+
+    AsyncComputeTaskPool::get()
+        .spawn(async move {
+            let pixels = scale_pixels(&tiles, tile_count.x as usize, scale).concat();
+            match save_png_pixels(&path, tile_count * scale, pixels) {
+                Ok(()) => info!("Exported {}", path.display()),
+                Err(error) => warn!("Failed to export {}: {}", path.display(), error),
+            }
+        })
+        .detach();
+}
+
+fn export_selection(
+    action_input: ActionInput,
+    selection: Res<Selection>,
+    export_settings: Res<ExportSettings>,
+    grid: FallingSandGridQuery,
+    palette: Res<ParticlePalette>,
+) {
+    if !action_input.just_pressed(Action::ExportSelection) {
+        return;
+    }
+    let Some(bounds) = selection.0 else {
+        return;
+    };
+
+    export("selection", bounds, &export_settings, &grid, &palette);
+}
+
+fn export_world(
+    action_input: ActionInput,
+    chunk_positions: Res<ChunkPositions>,
+    export_settings: Res<ExportSettings>,
+    grid: FallingSandGridQuery,
+    palette: Res<ParticlePalette>,
+) {
+    if !action_input.just_pressed(Action::ExportWorld) {
+        return;
+    }
+    let Some(bounds) = world_bounds(&chunk_positions) else {
+        return;
+    };
+
+    export("world", bounds, &export_settings, &grid, &palette);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_rasterize_orientation_and_scale() {
+        let tile_color = |IVec2 { x, y }: IVec2| [x as u8, y as u8, 0, 255];
+
+        let tiles = rasterize_tiles(IVec2::new(1, 1), IVec2::new(2, 3), tile_color);
+        let pixels = tiles.concat();
+        assert_eq!(pixels.len(), 2 * 3 * 4);
+        // The first row is the top of the rectangle
+        assert_eq!(pixels[0..8], [1, 3, 0, 255, 2, 3, 0, 255]);
+        assert_eq!(pixels[16..24], [1, 1, 0, 255, 2, 1, 0, 255]);
+
+        let pixels = scale_pixels(&tiles, 2, 2).concat();
+        assert_eq!(pixels.len(), 4 * 6 * 4);
+        let row = |row: usize| &pixels[row * 16..(row + 1) * 16];
+        assert_eq!(row(0), row(1));
+        assert_eq!(row(0)[0..8], [1, 3, 0, 255, 1, 3, 0, 255]);
+        assert_eq!(row(5)[8..16], [2, 1, 0, 255, 2, 1, 0, 255]);
+    }
+}
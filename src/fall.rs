@@ -1,23 +1,34 @@
 use rand::Rng;
 
-use bevy::{ecs::system::Res, log::info_span, math::IVec2};
+use bevy::{
+    ecs::system::{Res, ResMut},
+    log::info_span,
+    math::IVec2,
+    utils::Instant,
+};
 
 use crate::{
     chunk_neighborhood_view::ChunkNeighborhoodView,
+    falling_sand::{SimulationPass, SimulationPassTimings},
     material::{MaterialDensities, MaterialStates, StateOfMatter},
     particle_grid::Particle,
     process_chunks::{process_chunks_neighborhood, ChunksParam},
     util::{below, below_left, below_right, left, random_dir_range, right},
 };
 
+pub const MOMENTUM_GAIN: u16 = 4096;
+
 pub fn fall(
     grid: ChunksParam,
     material_states: Res<MaterialStates>,
     material_densities: Res<MaterialDensities>,
+    mut timings: ResMut<SimulationPassTimings>,
 ) {
+    let start = Instant::now();
     process_chunks_neighborhood(&grid, |_chunk_pos, grid| {
         fall_chunk(grid, &material_states, &material_densities)
     });
+    timings[SimulationPass::Fall] = start.elapsed();
 }
 
 pub fn fall_chunk(
@@ -27,7 +38,6 @@ pub fn fall_chunk(
 ) {
     let span = info_span!("fall_chunk");
     let _guard = span.enter();
-    const MOMEMTUM_GAIN: u16 = 4096;
     let chunk_size = grid.chunk_size();
     let min_y = 0;
     let max_y = chunk_size.y;
@@ -68,7 +78,7 @@ pub fn fall_chunk(
                 grid.center_chunk_mut()
                     .attributes_mut()
                     .momentum
-                    .set(particle.id(), MOMEMTUM_GAIN);
+                    .set(particle.id(), MOMENTUM_GAIN);
                 grid.swap_particles(particle_neighborhood_position, particle_below_position);
                 continue;
             }
@@ -109,7 +119,7 @@ pub fn fall_chunk(
             grid.center_chunk_mut()
                 .attributes_mut()
                 .momentum
-                .set(particle.id(), MOMEMTUM_GAIN);
+                .set(particle.id(), MOMENTUM_GAIN);
             grid.swap_particles(particle_neighborhood_position, other_particle_position);
         }
     }
@@ -1,4 +1,4 @@
-use std::time::Duration;
+use std::{fmt, time::Duration};
 
 use bevy::ecs::system::SystemParam;
 
@@ -6,8 +6,10 @@ use bevy::{prelude::*, render::render_asset::RenderAssetUsages, utils::HashSet};
 
 use bevy::render::extract_resource::{ExtractResource, ExtractResourcePlugin};
 use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat, TextureUsages};
+use bevy::render::renderer::RenderDevice;
 
 use bytemuck::cast_slice;
+use enum_map::EnumMap;
 use itertools::Itertools;
 use rand::{rngs::StdRng, SeedableRng};
 
@@ -19,11 +21,19 @@ use crate::{
     fall::fall,
     fire::fire_to_smoke,
     flow::flow,
-    material::{Material, MaterialColor, MaterialPlugin},
+    input_map::{Action, ActionInput},
+    material::{Material, MaterialColor, MaterialColorVariation, MaterialPlugin},
     process_chunks::ChunksParam,
     reactions::react,
-    render::{FallingSandImages, FallingSandRenderPlugin},
-    util::{chunk_neighbors, chunk_neighbors_n},
+    render::{
+        atlas::{assign_atlas_slots, spawn_chunk_atlas_mesh, update_chunk_atlas_mesh, ChunkAtlas},
+        compute_renderer_supported,
+        cpu::{CpuRenderPlugin, ParticlePalette},
+        FallingSandImages, FallingSandRenderPlugin,
+    },
+    time_control::SimulationTick,
+    util::{chunk_neighbors, chunk_neighbors_n, tile_pos_to_chunk_pos},
+    visible_chunks::{update_visible_chunks, VisibleChunks},
 };
 
 #[derive(Default)]
@@ -49,18 +59,39 @@ struct FallingSandPostSet;
 #[derive(Resource)]
 pub struct FallingSandRng(pub StdRng);
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Enum)]
+pub enum SimulationPass {
+    Fall,
+    Flow,
+    React,
+    FireToSmoke,
+}
+
+impl fmt::Display for SimulationPass {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SimulationPass::Fall => write!(f, "fall"),
+            SimulationPass::Flow => write!(f, "flow"),
+            SimulationPass::React => write!(f, "react"),
+            SimulationPass::FireToSmoke => write!(f, "fire_to_smoke"),
+        }
+    }
+}
+
+/// Duration of each simulation pass during the last tick.
+#[derive(Resource, Default, Deref, DerefMut)]
+pub struct SimulationPassTimings(pub EnumMap<SimulationPass, Duration>);
+
 impl Plugin for FallingSandPlugin {
     fn build(&self, app: &mut App) {
         app.add_plugins((
             ExtractResourcePlugin::<FallingSandImages>::default(),
             ExtractResourcePlugin::<FallingSandSettings>::default(),
+            ExtractResourcePlugin::<ChunkAtlas>::default(),
+            ExtractResourcePlugin::<VisibleChunks>::default(),
             MaterialPlugin,
-            FallingSandRenderPlugin,
         ))
         .register_type::<DirtyChunks>()
-        .insert_resource(Time::<Virtual>::from_max_delta(Duration::from_secs_f32(
-            1. / 64.,
-        )))
         .insert_resource(self.settings.clone())
         .insert_resource(FallingSandRng(StdRng::seed_from_u64(0)))
         .init_resource::<ChunkPositions>()
@@ -68,7 +99,23 @@ impl Plugin for FallingSandPlugin {
         .init_resource::<ActiveChunks>()
         .init_resource::<FallingSandImages>()
         .init_resource::<ChunkDebug>()
-        .add_systems(Startup, setup.before(FallingSandPreSet))
+        .init_resource::<SimulationPassTimings>()
+        .init_resource::<ParticlePalette>()
+        .init_resource::<VisibleChunks>()
+        .init_resource::<SimulationTick>()
+        .add_systems(
+            Startup,
+            (setup, spawn_chunk_atlas_mesh).before(FallingSandPreSet),
+        )
+        .add_systems(
+            PostUpdate,
+            (
+                update_visible_chunks,
+                assign_atlas_slots,
+                update_chunk_atlas_mesh,
+            )
+                .chain(),
+        )
         .add_systems(
             FixedPreUpdate,
             (
@@ -104,6 +151,35 @@ impl Plugin for FallingSandPlugin {
             ),
         );
     }
+
+    /// The render device is only known once the plugins are finished, so this is where the
+    /// renderer is picked.
+    fn finish(&self, app: &mut App) {
+        let renderer = self.settings.renderer.unwrap_or_else(|| {
+            let supported = app
+                .world
+                .get_resource::<RenderDevice>()
+                .is_some_and(compute_renderer_supported);
+            if supported {
+                ChunkRenderer::Gpu
+            } else {
+                info!("Render device doesn't support the compute renderer, rendering on the CPU");
+                ChunkRenderer::Cpu
+            }
+        });
+        app.insert_resource(renderer).init_resource::<ChunkAtlas>();
+
+        // Plugins added while the app is finishing aren't finished by it, so this is done here
+        match renderer {
+            ChunkRenderer::Gpu => {
+                FallingSandRenderPlugin.build(app);
+                FallingSandRenderPlugin.finish(app);
+            }
+            ChunkRenderer::Cpu => {
+                CpuRenderPlugin.build(app);
+            }
+        }
+    }
 }
 
 fn clean_particles(chunk_query: Query<&Chunk>) {
@@ -125,8 +201,17 @@ fn clean_particles_chunk(grid: &mut ChunkData) {
         });
 }
 
-fn clean_chunks(chunk_query: Query<&Chunk>) {
-    chunk_query.par_iter().for_each(|chunk| {
+fn clean_chunks(
+    chunk_query: Query<(&Chunk, &ChunkPosition)>,
+    visible_chunks: Res<VisibleChunks>,
+    simulation_tick: Res<SimulationTick>,
+    falling_sand_settings: Res<FallingSandSettings>,
+) {
+    chunk_query.par_iter().for_each(|(chunk, position)| {
+        // Chunks that skip this tick stay dirty, so they're still active when simulated next
+        if !visible_chunks.is_simulated(position.0, simulation_tick.0, &falling_sand_settings) {
+            return;
+        }
         let chunk_data = &mut chunk.write().unwrap();
         if !chunk_data.is_dirty() {
             return;
@@ -176,10 +261,29 @@ pub struct ChunkParticleGridImage {
     pub materials_texture: Handle<Image>,
 }
 
+/// How the chunk sprites are colored from the particle grids. The renderer in use is inserted
+/// as a resource when the `FallingSandPlugin` finishes.
+#[derive(Resource, Clone, Copy, Default, Debug, PartialEq, Eq, Reflect)]
+pub enum ChunkRenderer {
+    /// Compute shader, requires storage texture support.
+    #[default]
+    Gpu,
+    /// Fallback for devices without compute support.
+    Cpu,
+}
+
 #[derive(Resource, Clone, ExtractResource, Reflect)]
 pub struct FallingSandSettings {
     pub size: (usize, usize),
     pub tile_size: u32,
+    /// Renderer to use regardless of the render device, by default it's picked from what the
+    /// device supports.
+    pub renderer: Option<ChunkRenderer>,
+    /// Active chunks further than `distant_chunk_distance` chunks from the screen are only
+    /// simulated every `distant_tick_interval` ticks. An interval of 1 simulates every chunk in
+    /// every tick.
+    pub distant_tick_interval: u32,
+    pub distant_chunk_distance: i32,
 }
 
 impl Default for FallingSandSettings {
@@ -187,6 +291,9 @@ impl Default for FallingSandSettings {
         FallingSandSettings {
             size: (CHUNK_SIZE as usize, CHUNK_SIZE as usize),
             tile_size: 1,
+            renderer: None,
+            distant_tick_interval: 1,
+            distant_chunk_distance: 4,
         }
     }
 }
@@ -201,13 +308,8 @@ fn chunk_debug_enabled(terrain_debug: Res<ChunkDebug>) -> bool {
     terrain_debug.0
 }
 
-const TERRAIN_DEBUG_TOGGLE_KEY: KeyCode = KeyCode::F3;
-
-fn toggle_chunk_debug(
-    keyboard_input: Res<ButtonInput<KeyCode>>,
-    mut terrain_debug: ResMut<ChunkDebug>,
-) {
-    if keyboard_input.just_pressed(TERRAIN_DEBUG_TOGGLE_KEY) {
+fn toggle_chunk_debug(action_input: ActionInput, mut terrain_debug: ResMut<ChunkDebug>) {
+    if action_input.just_pressed(Action::ToggleChunkDebug) {
         terrain_debug.0 = !terrain_debug.0;
     }
 }
@@ -250,19 +352,27 @@ pub struct ChunkCreationParams<'w, 's> {
     commands: Commands<'w, 's>,
     images: ResMut<'w, Assets<Image>>,
     falling_sand_settings: Res<'w, FallingSandSettings>,
-    material_colors: Res<'w, MaterialColor>,
     pub chunk_positions: ResMut<'w, ChunkPositions>,
     pub chunk_data_positions: ResMut<'w, ChunkDataPositions>,
 }
 
 impl<'w, 's> ChunkCreationParams<'w, 's> {
+    /// Spawn the chunks containing `tile_positions` that aren't spawned yet.
+    pub fn spawn_chunks_under(&mut self, tile_positions: impl IntoIterator<Item = IVec2>) {
+        let unspawned_chunk_positions = tile_positions
+            .into_iter()
+            .map(tile_pos_to_chunk_pos)
+            .unique()
+            .filter(|pos| !self.chunk_positions.contains(*pos))
+            .collect_vec();
+        self.spawn_chunks(unspawned_chunk_positions);
+    }
+
     pub fn spawn_chunks(&mut self, positions: impl IntoIterator<Item = IVec2>) {
-        let initial_material = Material::Air;
         positions.into_iter().for_each(|position| {
             let chunk_bundle = {
                 let images: &mut Assets<Image> = &mut self.images;
                 let falling_sand_settings: &FallingSandSettings = &self.falling_sand_settings;
-                let material_colors: &MaterialColor = &self.material_colors;
                 let IVec2 { x, y } = position;
                 let size = (
                     falling_sand_settings.size.0 as u32,
@@ -281,32 +391,20 @@ impl<'w, 's> ChunkCreationParams<'w, 's> {
                     Chunk::new_with_material((size.0 as usize, size.1 as usize), material, rng);
                 self.chunk_data_positions.add(position, chunk.clone());
 
-                let initial_color = material_colors[initial_material];
-
-                let (grid_texture, color_image) =
-                    create_chunk_images(size, &chunk.read().unwrap(), images, initial_color);
+                let grid_texture = create_particle_grid_image(size, &chunk.read().unwrap(), images);
 
                 (
                     Name::new("Chunk"),
-                    SpriteBundle {
-                        sprite: Sprite {
-                            custom_size: Some(Vec2::new(
-                                (size.0 * scale) as f32,
-                                (size.1 * scale) as f32,
+                    // Drawn by the chunk atlas mesh, the transform places children like the
+                    // debug overlays on the chunk
+                    SpatialBundle::from_transform(
+                        Transform::from_rotation(Quat::from_rotation_z(std::f32::consts::PI / 2.0))
+                            .with_translation(Vec3::new(
+                                (x * size.0 as i32 * scale as i32) as f32,
+                                (y * size.1 as i32 * scale as i32) as f32,
+                                0.0,
                             )),
-                            ..default()
-                        },
-                        texture: color_image,
-                        transform: Transform::from_rotation(Quat::from_rotation_z(
-                            std::f32::consts::PI / 2.0,
-                        ))
-                        .with_translation(Vec3::new(
-                            (x * size.0 as i32 * scale as i32) as f32,
-                            (y * size.1 as i32 * scale as i32) as f32,
-                            0.0,
-                        )),
-                        ..default()
-                    },
+                    ),
                     ChunkParticleGridImage {
                         materials_texture: grid_texture,
                     },
@@ -324,9 +422,10 @@ impl<'w, 's> ChunkCreationParams<'w, 's> {
 fn setup(
     mut chunk_creation_params: ChunkCreationParams,
     material_colors: Res<MaterialColor>,
+    material_color_variations: Res<MaterialColorVariation>,
     mut falling_sand_images: ResMut<FallingSandImages>,
 ) {
-    let color_map_image = create_color_map_image(&material_colors);
+    let color_map_image = create_color_map_image(&material_colors, &material_color_variations);
     falling_sand_images.color_map = chunk_creation_params.images.add(color_map_image);
     let radius = 10;
     let chunk_positions = (-radius..=radius)
@@ -335,13 +434,11 @@ fn setup(
     chunk_creation_params.spawn_chunks(chunk_positions);
 }
 
-fn create_chunk_images(
+fn create_particle_grid_image(
     size: (u32, u32),
     falling_sand_grid: &ChunkData,
     images: &mut Assets<Image>,
-    initial_color: Color,
-) -> (Handle<Image>, Handle<Image>) {
-    // Create the particle grid texture
+) -> Handle<Image> {
     let mut grid_image = Image::new_fill(
         Extent3d {
             width: size.0,
@@ -351,49 +448,38 @@ fn create_chunk_images(
         TextureDimension::D2,
         &[0u8; 4],
         TextureFormat::R32Uint,
-        RenderAssetUsages::MAIN_WORLD | RenderAssetUsages::RENDER_WORLD,
+        // Kept up to date by `extract`, which writes the changed particles into the texture
+        RenderAssetUsages::RENDER_WORLD,
     );
-    grid_image.texture_descriptor.usage = TextureUsages::COPY_DST | TextureUsages::STORAGE_BINDING;
+    grid_image.texture_descriptor.usage = TextureUsages::COPY_DST | TextureUsages::TEXTURE_BINDING;
     grid_image.texture_descriptor.label = Some("chunk_texture");
 
     grid_image.data.copy_from_slice(cast_slice(
         falling_sand_grid.particles().array().as_slice().unwrap(),
     ));
 
-    // Create the render target texture
-    let mut render_target = Image::new_fill(
-        Extent3d {
-            width: size.0,
-            height: size.1,
-            depth_or_array_layers: 1,
-        },
-        TextureDimension::D2,
-        cast_slice(&initial_color.as_linear_rgba_f32()),
-        TextureFormat::Rgba32Float,
-        RenderAssetUsages::RENDER_WORLD,
-    );
-    render_target.texture_descriptor.usage =
-        TextureUsages::STORAGE_BINDING | TextureUsages::TEXTURE_BINDING;
-
-    // Add the textures to the asset server and get the handles
-    let grid_texture = images.add(grid_image);
-    let color_image = images.add(render_target);
-    (grid_texture, color_image)
+    images.add(grid_image)
 }
 
-fn create_color_map_image(material_colors: &MaterialColor) -> Image {
+/// Color gradients of the materials, the first row holds the `MaterialColor` and the second row
+/// the `MaterialColorVariation` of every material.
+fn create_color_map_image(
+    material_colors: &MaterialColor,
+    material_color_variations: &MaterialColorVariation,
+) -> Image {
     let material_colors_vec = material_colors
         .values()
+        .chain(material_color_variations.values())
         .flat_map(|c| c.as_rgba_u8())
         .collect::<Vec<u8>>();
 
     let mut color_map_image = Image::new(
         Extent3d {
-            height: 1,
+            height: 2,
             width: material_colors.0.len() as u32,
             depth_or_array_layers: 1,
         },
-        TextureDimension::D1,
+        TextureDimension::D2,
         material_colors_vec,
         TextureFormat::Rgba8UnormSrgb,
         RenderAssetUsages::RENDER_WORLD,
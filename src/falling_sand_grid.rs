@@ -13,6 +13,9 @@ use crate::{
     consts::CHUNK_SIZE,
     falling_sand::ChunkPositions,
     material::Material,
+    particle_attributes::ParticleAttributeValues,
+    particle_grid::Particle,
+    particle_region::RegionParticle,
     util::{positive_mod, tile_pos_to_chunk_pos},
 };
 
@@ -32,6 +35,69 @@ impl<'w, 's> FallingSandGridQuery<'w, 's> {
         self.chunks.get(chunk_entity).unwrap().clone().0.clone()
     }
 
+    /// Particle at `position`, or `None` if there's no chunk spawned there.
+    pub fn get_particle(&self, position: IVec2) -> Option<Particle> {
+        let chunk_position = tile_pos_to_chunk_pos(position);
+        let chunk_entity = self.get_chunk_entity_at(chunk_position)?;
+        let chunk = self.chunks.get(chunk_entity).ok()?;
+        let chunk_data = chunk.read().unwrap();
+        chunk_data
+            .get_particle(IVec2::new(
+                positive_mod(position.x, CHUNK_SIZE),
+                positive_mod(position.y, CHUNK_SIZE),
+            ))
+            .copied()
+    }
+
+    /// Particle at `position` together with its attributes, or `None` if there's no chunk
+    /// spawned there.
+    pub fn get_particle_with_attributes(
+        &self,
+        position: IVec2,
+    ) -> Option<(Particle, ParticleAttributeValues)> {
+        let chunk_position = tile_pos_to_chunk_pos(position);
+        let chunk_entity = self.get_chunk_entity_at(chunk_position)?;
+        let chunk = self.chunks.get(chunk_entity).ok()?;
+        let chunk_data = chunk.read().unwrap();
+        let particle = *chunk_data.get_particle(IVec2::new(
+            positive_mod(position.x, CHUNK_SIZE),
+            positive_mod(position.y, CHUNK_SIZE),
+        ))?;
+        Some((particle, chunk_data.attributes().get_values(particle.id())))
+    }
+
+    /// Replace the particle at `position` with `region_particle`, keeping its color seed if it
+    /// has one.
+    pub fn set_region_particle(&mut self, position: IVec2, region_particle: &RegionParticle) {
+        let chunk_position = tile_pos_to_chunk_pos(position);
+        let chunk = self.get_chunk_data(chunk_position);
+        let mut chunk_data = chunk.write().unwrap();
+        let local_position = IVec2::new(
+            positive_mod(position.x, CHUNK_SIZE),
+            positive_mod(position.y, CHUNK_SIZE),
+        );
+        chunk_data.set_particle_material(local_position, region_particle.material);
+        let particle = chunk_data.get_particle_mut(local_position).unwrap();
+        if let Some(color_seed) = region_particle.color_seed {
+            particle.set_color_seed(color_seed);
+        }
+        let particle_id = particle.id();
+        chunk_data
+            .attributes_mut()
+            .set_values(particle_id, region_particle.attributes.clone());
+    }
+
+    /// Mark the chunk containing `position` as dirty, keeping it active for the next tick.
+    pub fn wake_chunk(&self, position: IVec2) {
+        let chunk_position = tile_pos_to_chunk_pos(position);
+        let Some(chunk_entity) = self.get_chunk_entity_at(chunk_position) else {
+            return;
+        };
+        if let Ok(chunk) = self.chunks.get(chunk_entity) {
+            chunk.write().unwrap().set_dirty(true);
+        }
+    }
+
     pub fn set_particle(&mut self, position: IVec2, material: Material) {
         let chunk_position = tile_pos_to_chunk_pos(position);
         let chunk = self.get_chunk_data(chunk_position);
@@ -1,13 +1,16 @@
-use bevy::{log::info_span, math::IVec2};
+use bevy::{ecs::system::ResMut, log::info_span, math::IVec2, utils::Instant};
 use rand::Rng;
 
 use crate::{
+    falling_sand::{SimulationPass, SimulationPassTimings},
     material::Material,
     process_chunks::{process_chunks_dense, ChunksParam},
 };
 
-pub fn fire_to_smoke(grid: ChunksParam) {
+pub fn fire_to_smoke(grid: ChunksParam, mut timings: ResMut<SimulationPassTimings>) {
+    let start = Instant::now();
     process_chunks_dense(&grid, |_, chunk| fire_to_smoke_chunk(chunk));
+    timings[SimulationPass::FireToSmoke] = start.elapsed();
 }
 
 pub fn fire_to_smoke_chunk(chunk: &mut crate::chunk::ChunkData) {
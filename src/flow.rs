@@ -1,9 +1,15 @@
 use rand::Rng;
 
-use bevy::{ecs::system::Res, log::info_span, math::IVec2};
+use bevy::{
+    ecs::system::{Res, ResMut},
+    log::info_span,
+    math::IVec2,
+    utils::Instant,
+};
 
 use crate::{
     chunk_neighborhood_view::ChunkNeighborhoodView,
+    falling_sand::{SimulationPass, SimulationPassTimings},
     material::{MaterialDensities, MaterialFlowing, MaterialStates, StateOfMatter},
     process_chunks::{process_chunks_neighborhood, ChunksParam},
     util::{below, left, random_dir_range, right},
@@ -13,7 +19,9 @@ pub fn flow(
     material_states: Res<MaterialStates>,
     material_densities: Res<MaterialDensities>,
     material_flowing: Res<MaterialFlowing>,
+    mut timings: ResMut<SimulationPassTimings>,
 ) {
+    let start = Instant::now();
     process_chunks_neighborhood(&grid, |_chunk_pos, grid| {
         flow_chunk(
             grid,
@@ -22,6 +30,7 @@ pub fn flow(
             &material_states,
         )
     });
+    timings[SimulationPass::Flow] = start.elapsed();
 }
 
 pub fn flow_chunk(
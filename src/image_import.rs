@@ -0,0 +1,242 @@
+use std::{
+    collections::HashMap,
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use bevy::{
+    app::{App, Plugin, Startup, Update},
+    ecs::{
+        event::EventReader,
+        system::{Commands, Res, ResMut, Resource},
+    },
+    log::{info, warn},
+    math::IVec2,
+    render::{
+        render_asset::RenderAssetUsages,
+        render_resource::TextureFormat,
+        texture::{CompressedImageFormats, Image, ImageSampler, ImageType},
+    },
+    window::FileDragAndDrop,
+};
+
+use crate::{
+    draw_tool::{Tool, ToolState},
+    material::{Material, MaterialColor, MaterialColorVariation},
+    particle_attributes::ParticleAttributeValues,
+    particle_region::{ParticleRegion, RegionParticle},
+    selection::{Clipboard, Paste},
+};
+
+pub struct ImageImportPlugin {
+    pub settings: ImageImportSettings,
+}
+
+impl Plugin for ImageImportPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(self.settings.clone())
+            .add_systems(Startup, import_world)
+            .add_systems(Update, import_dropped_images);
+    }
+}
+
+#[derive(Resource, Clone, Default)]
+pub struct ImageImportSettings {
+    /// Image that is loaded as the world at startup, centered on the origin. Images dropped on
+    /// the window are put on the clipboard instead.
+    pub world: Option<PathBuf>,
+}
+
+/// Extension of the optional palette file next to an image, e.g. `cave.palette.ron` for
+/// `cave.png`. It maps materials to the color they're drawn with, as in
+/// `{Sand: (255, 200, 0), Water: (0, 0, 255)}`.
+const PALETTE_EXTENSION: &str = "palette.ron";
+
+/// Colors that are turned into a material when importing an image.
+struct ImportPalette(Vec<([u8; 3], Material)>);
+
+impl ImportPalette {
+    /// Both ends of the color gradient of every material, so exported images import back to the
+    /// same materials.
+    fn from_material_colors(
+        material_colors: &MaterialColor,
+        material_color_variations: &MaterialColorVariation,
+    ) -> ImportPalette {
+        ImportPalette(
+            material_colors
+                .iter()
+                .chain(material_color_variations.iter())
+                .map(|(material, color)| {
+                    let [r, g, b, _] = color.as_rgba_u8();
+                    ([r, g, b], material)
+                })
+                .collect(),
+        )
+    }
+
+    fn load(path: &Path) -> Result<ImportPalette, Box<dyn Error>> {
+        let colors: HashMap<Material, (u8, u8, u8)> = ron::from_str(&fs::read_to_string(path)?)?;
+        Ok(ImportPalette(
+            colors
+                .into_iter()
+                .map(|(material, (r, g, b))| ([r, g, b], material))
+                .collect(),
+        ))
+    }
+
+    /// Material with the color closest to the pixel. Transparent pixels are air.
+    fn nearest_material(&self, [r, g, b, a]: [u8; 4]) -> Material {
+        if a < u8::MAX / 2 {
+            return Material::Air;
+        }
+
+        self.0
+            .iter()
+            .min_by_key(|(color, _)| {
+                [r, g, b]
+                    .iter()
+                    .zip(color)
+                    .map(|(a, b)| (*a as i32 - *b as i32).pow(2))
+                    .sum::<i32>()
+            })
+            .map_or(Material::Air, |(_, material)| *material)
+    }
+}
+
+/// Region of the particles drawn in `pixels`, RGBA rows of `width` pixels starting at the top
+/// row.
+fn region_from_pixels(pixels: &[u8], width: i32, palette: &ImportPalette) -> ParticleRegion {
+    let height = pixels.len() as i32 / 4 / width;
+    ParticleRegion::from_fn(IVec2::new(width, height), |IVec2 { x, y }| {
+        let index = ((height - 1 - y) * width + x) as usize * 4;
+        RegionParticle {
+            material: palette.nearest_material(pixels[index..index + 4].try_into().unwrap()),
+            color_seed: None,
+            attributes: ParticleAttributeValues::default(),
+        }
+    })
+}
+
+pub fn load_image_file(
+    path: &Path,
+    material_colors: &MaterialColor,
+    material_color_variations: &MaterialColorVariation,
+) -> Result<ParticleRegion, Box<dyn Error>> {
+    let extension = path
+        .extension()
+        .map(|extension| extension.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let image = Image::from_buffer(
+        &fs::read(path)?,
+        ImageType::Extension(&extension),
+        CompressedImageFormats::NONE,
+        true,
+        ImageSampler::Default,
+        RenderAssetUsages::MAIN_WORLD,
+    )?
+    .convert(TextureFormat::Rgba8UnormSrgb)
+    .ok_or("unsupported pixel format")?;
+
+    let palette_path = path.with_extension(PALETTE_EXTENSION);
+    let palette = if palette_path.exists() {
+        ImportPalette::load(&palette_path)?
+    } else {
+        ImportPalette::from_material_colors(material_colors, material_color_variations)
+    };
+
+    Ok(region_from_pixels(
+        &image.data,
+        image.width() as i32,
+        &palette,
+    ))
+}
+
+fn import_world(
+    mut commands: Commands,
+    image_import_settings: Res<ImageImportSettings>,
+    material_colors: Res<MaterialColor>,
+    material_color_variations: Res<MaterialColorVariation>,
+) {
+    let Some(path) = &image_import_settings.world else {
+        return;
+    };
+
+    match load_image_file(path, &material_colors, &material_color_variations) {
+        Ok(region) => {
+            info!("Imported world {}", path.display());
+            commands.spawn(Paste {
+                origin: -region.size() / 2,
+                region,
+                brush_rules: false,
+                skip_air: false,
+            });
+        }
+        Err(error) => warn!("Failed to import world {}: {}", path.display(), error),
+    }
+}
+
+fn import_dropped_images(
+    mut file_drag_and_drop_events: EventReader<FileDragAndDrop>,
+    material_colors: Res<MaterialColor>,
+    material_color_variations: Res<MaterialColorVariation>,
+    mut clipboard: ResMut<Clipboard>,
+    mut tool_state: ResMut<ToolState>,
+) {
+    for event in file_drag_and_drop_events.read() {
+        let FileDragAndDrop::DroppedFile { path_buf, .. } = event else {
+            continue;
+        };
+
+        match load_image_file(path_buf, &material_colors, &material_color_variations) {
+            Ok(region) => {
+                info!("Imported {}", path_buf.display());
+                clipboard.region = Some(region);
+                clipboard.skip_air = false;
+                tool_state.tool = Tool::Paste;
+            }
+            Err(error) => warn!("Failed to import {}: {}", path_buf.display(), error),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_nearest_material() {
+        let palette = ImportPalette(vec![
+            ([200, 180, 100], Material::Sand),
+            ([20, 40, 220], Material::Water),
+        ]);
+
+        assert_eq!(
+            palette.nearest_material([190, 170, 120, 255]),
+            Material::Sand
+        );
+        assert_eq!(palette.nearest_material([0, 0, 255, 255]), Material::Water);
+        assert_eq!(palette.nearest_material([0, 0, 255, 0]), Material::Air);
+    }
+
+    #[test]
+    fn test_region_from_pixels_is_upright() {
+        let palette = ImportPalette(vec![
+            ([255, 255, 255], Material::Sand),
+            ([0, 0, 0], Material::Bedrock),
+        ]);
+        // Top row white, bottom row black
+        let pixels = [[255; 4], [255; 4], [0, 0, 0, 255], [0, 0, 0, 255]].concat();
+
+        let region = region_from_pixels(&pixels, 2, &palette);
+        assert_eq!(region.size(), IVec2::new(2, 2));
+        assert_eq!(
+            region.get(IVec2::new(1, 0)).unwrap().material,
+            Material::Bedrock
+        );
+        assert_eq!(
+            region.get(IVec2::new(0, 1)).unwrap().material,
+            Material::Sand
+        );
+    }
+}
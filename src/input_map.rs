@@ -0,0 +1,453 @@
+use std::{collections::HashMap, error::Error, fs, path::Path};
+
+use bevy::{
+    app::{App, Plugin, PreStartup},
+    ecs::system::{Res, ResMut, Resource, SystemParam},
+    input::{
+        gamepad::{GamepadAxis, GamepadAxisType, GamepadButton, GamepadButtonType, Gamepads},
+        keyboard::KeyCode,
+        mouse::MouseButton,
+        Axis, ButtonInput,
+    },
+    log::{info, warn},
+    math::Vec2,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::material::Material;
+
+pub struct InputMapPlugin;
+
+impl Plugin for InputMapPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<InputMap>()
+            .add_systems(PreStartup, load_input_map);
+    }
+}
+
+/// Bindings in this file replace the default bindings of the same action, e.g.
+/// `{ Pan: [(button: Mouse(Right))], Copy: [(button: Key(KeyC), modifiers: [Alt])] }`
+const INPUT_MAP_PATH: &str = "input.ron";
+
+/// Everything that can be bound to a key or mouse button.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Action {
+    Draw,
+    /// Draw air with the brush.
+    Erase,
+    /// Remove the emitter or sink under the cursor.
+    Remove,
+    PickMaterial,
+    Pan,
+    /// Held to make the mouse wheel change the brush size instead of zooming.
+    BrushSizeModifier,
+    /// Held to make the mouse wheel change the spray density instead of zooming.
+    SprayDensityModifier,
+    SelectMaterial(Material),
+    NextMaterial,
+    PreviousMaterial,
+    IncreaseBrushSize,
+    DecreaseBrushSize,
+    Copy,
+    Cut,
+    Paste,
+    SaveStamp,
+    ExportSelection,
+    ExportWorld,
+    Rotate,
+    MirrorHorizontal,
+    MirrorVertical,
+    ToggleStepping,
+    StepFrame,
+    ContinueFrame,
+    PrintStepping,
+    SpeedUp,
+    SlowDown,
+    ToggleChunkDebug,
+    ToggleParticleInspector,
+    ToggleStats,
+    CycleDebugOverlay,
+    /// Recording isn't available in the web build, so this isn't bound there.
+    ToggleRecording,
+    CycleAmbientLight,
+}
+
+/// Modifier keys, either the left or the right key counts.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Modifier {
+    Control,
+    Shift,
+    Alt,
+}
+
+impl Modifier {
+    fn keys(self) -> [KeyCode; 2] {
+        match self {
+            Modifier::Control => [KeyCode::ControlLeft, KeyCode::ControlRight],
+            Modifier::Shift => [KeyCode::ShiftLeft, KeyCode::ShiftRight],
+            Modifier::Alt => [KeyCode::AltLeft, KeyCode::AltRight],
+        }
+    }
+}
+
+/// Gamepad buttons match the button on any connected gamepad.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum InputButton {
+    Key(KeyCode),
+    Mouse(MouseButton),
+    Gamepad(GamepadButtonType),
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Binding {
+    pub button: InputButton,
+    #[serde(default)]
+    pub modifiers: Vec<Modifier>,
+}
+
+impl Binding {
+    fn key(key: KeyCode) -> Binding {
+        Binding {
+            button: InputButton::Key(key),
+            modifiers: Vec::new(),
+        }
+    }
+
+    fn mouse(button: MouseButton) -> Binding {
+        Binding {
+            button: InputButton::Mouse(button),
+            modifiers: Vec::new(),
+        }
+    }
+
+    fn gamepad(button: GamepadButtonType) -> Binding {
+        Binding {
+            button: InputButton::Gamepad(button),
+            modifiers: Vec::new(),
+        }
+    }
+
+    fn with(mut self, modifier: Modifier) -> Binding {
+        self.modifiers.push(modifier);
+        self
+    }
+}
+
+#[derive(Resource, Debug)]
+pub struct InputMap(pub HashMap<Action, Vec<Binding>>);
+
+impl Default for InputMap {
+    fn default() -> Self {
+        let material_keys = [
+            (KeyCode::Digit1, Material::Sand),
+            (KeyCode::Digit2, Material::Water),
+            (KeyCode::Digit3, Material::Fire),
+            (KeyCode::Digit4, Material::Wood),
+            (KeyCode::Digit5, Material::Bedrock),
+            (KeyCode::Digit6, Material::Oil),
+        ]
+        .map(|(key, material)| (Action::SelectMaterial(material), Binding::key(key)));
+
+        let bindings = [
+            (Action::Draw, Binding::mouse(MouseButton::Left)),
+            (
+                Action::Draw,
+                Binding::gamepad(GamepadButtonType::RightTrigger2),
+            ),
+            (
+                Action::Erase,
+                Binding::gamepad(GamepadButtonType::LeftTrigger2),
+            ),
+            (Action::Remove, Binding::mouse(MouseButton::Right)),
+            (
+                Action::PickMaterial,
+                Binding::mouse(MouseButton::Left).with(Modifier::Alt),
+            ),
+            (Action::Pan, Binding::mouse(MouseButton::Middle)),
+            (
+                Action::BrushSizeModifier,
+                Binding::key(KeyCode::ControlLeft),
+            ),
+            (
+                Action::SprayDensityModifier,
+                Binding::key(KeyCode::ShiftLeft),
+            ),
+            (
+                Action::Copy,
+                Binding::key(KeyCode::KeyC).with(Modifier::Control),
+            ),
+            (
+                Action::Cut,
+                Binding::key(KeyCode::KeyX).with(Modifier::Control),
+            ),
+            (
+                Action::Paste,
+                Binding::key(KeyCode::KeyV).with(Modifier::Control),
+            ),
+            (
+                Action::SaveStamp,
+                Binding::key(KeyCode::KeyS).with(Modifier::Control),
+            ),
+            (
+                Action::NextMaterial,
+                Binding::gamepad(GamepadButtonType::RightTrigger),
+            ),
+            (
+                Action::PreviousMaterial,
+                Binding::gamepad(GamepadButtonType::LeftTrigger),
+            ),
+            (
+                Action::IncreaseBrushSize,
+                Binding::key(KeyCode::BracketRight),
+            ),
+            (
+                Action::IncreaseBrushSize,
+                Binding::gamepad(GamepadButtonType::DPadUp),
+            ),
+            (
+                Action::DecreaseBrushSize,
+                Binding::key(KeyCode::BracketLeft),
+            ),
+            (
+                Action::DecreaseBrushSize,
+                Binding::gamepad(GamepadButtonType::DPadDown),
+            ),
+            (
+                Action::ExportSelection,
+                Binding::key(KeyCode::KeyE).with(Modifier::Control),
+            ),
+            (
+                Action::ExportWorld,
+                Binding::key(KeyCode::KeyE)
+                    .with(Modifier::Control)
+                    .with(Modifier::Shift),
+            ),
+            (Action::Rotate, Binding::key(KeyCode::KeyR)),
+            (Action::MirrorHorizontal, Binding::key(KeyCode::KeyM)),
+            (
+                Action::MirrorVertical,
+                Binding::key(KeyCode::KeyM).with(Modifier::Shift),
+            ),
+            (Action::ToggleStepping, Binding::key(KeyCode::Backquote)),
+            (Action::StepFrame, Binding::key(KeyCode::KeyS)),
+            (Action::ContinueFrame, Binding::key(KeyCode::Space)),
+            (Action::PrintStepping, Binding::key(KeyCode::Slash)),
+            (Action::SpeedUp, Binding::key(KeyCode::Equal)),
+            (Action::SlowDown, Binding::key(KeyCode::Minus)),
+            (Action::ToggleChunkDebug, Binding::key(KeyCode::F3)),
+            (Action::ToggleParticleInspector, Binding::key(KeyCode::F4)),
+            (Action::ToggleStats, Binding::key(KeyCode::F5)),
+            (Action::CycleDebugOverlay, Binding::key(KeyCode::F6)),
+            #[cfg(not(target_arch = "wasm32"))]
+            (Action::ToggleRecording, Binding::key(KeyCode::F9)),
+            (Action::CycleAmbientLight, Binding::key(KeyCode::KeyL)),
+        ];
+
+        let mut input_map = HashMap::new();
+        for (action, binding) in bindings.into_iter().chain(material_keys) {
+            input_map
+                .entry(action)
+                .or_insert_with(Vec::new)
+                .push(binding);
+        }
+        InputMap(input_map)
+    }
+}
+
+impl InputMap {
+    /// Replace the bindings of every action listed in `config`.
+    fn apply_config(&mut self, config: &str) -> Result<(), Box<dyn Error>> {
+        let bindings: HashMap<Action, Vec<Binding>> = ron::from_str(config)?;
+        self.0.extend(bindings);
+        Ok(())
+    }
+
+    /// Whether `binding` is triggered, given which buttons and modifiers are active. A binding
+    /// on the same button that needs more modifiers takes precedence, so `S` and `Ctrl+S` can
+    /// trigger different actions.
+    fn is_binding_active(
+        &self,
+        binding: &Binding,
+        is_button_active: impl Fn(InputButton) -> bool,
+        is_modifier_held: impl Fn(Modifier) -> bool,
+    ) -> bool {
+        let is_held = |binding: &Binding| binding.modifiers.iter().all(|m| is_modifier_held(*m));
+        if !is_button_active(binding.button) || !is_held(binding) {
+            return false;
+        }
+
+        !self.0.values().flatten().any(|other| {
+            other.button == binding.button
+                && other.modifiers.len() > binding.modifiers.len()
+                && binding
+                    .modifiers
+                    .iter()
+                    .all(|m| other.modifiers.contains(m))
+                && is_held(other)
+        })
+    }
+}
+
+fn load_input_map(mut input_map: ResMut<InputMap>) {
+    let path = Path::new(INPUT_MAP_PATH);
+    if !path.exists() {
+        return;
+    }
+
+    match fs::read_to_string(path)
+        .map_err(Into::into)
+        .and_then(|config| input_map.apply_config(&config))
+    {
+        Ok(()) => info!("Loaded input bindings from {}", path.display()),
+        Err(error) => warn!("Failed to load {}: {}", path.display(), error),
+    }
+}
+
+/// Keyboard and mouse input, read as actions through the `InputMap`.
+#[derive(SystemParam)]
+pub struct ActionInput<'w> {
+    input_map: Res<'w, InputMap>,
+    keyboard_input: Res<'w, ButtonInput<KeyCode>>,
+    mouse_button_input: Res<'w, ButtonInput<MouseButton>>,
+    gamepad_button_input: Res<'w, ButtonInput<GamepadButton>>,
+    gamepads: Res<'w, Gamepads>,
+}
+
+impl<'w> ActionInput<'w> {
+    pub fn pressed(&self, action: Action) -> bool {
+        self.is_active(action, |button| match button {
+            InputButton::Key(key) => self.keyboard_input.pressed(key),
+            InputButton::Mouse(button) => self.mouse_button_input.pressed(button),
+            InputButton::Gamepad(button) => {
+                self.any_gamepad(button, |b| self.gamepad_button_input.pressed(b))
+            }
+        })
+    }
+
+    pub fn just_pressed(&self, action: Action) -> bool {
+        self.is_active(action, |button| match button {
+            InputButton::Key(key) => self.keyboard_input.just_pressed(key),
+            InputButton::Mouse(button) => self.mouse_button_input.just_pressed(button),
+            InputButton::Gamepad(button) => {
+                self.any_gamepad(button, |b| self.gamepad_button_input.just_pressed(b))
+            }
+        })
+    }
+
+    pub fn just_released(&self, action: Action) -> bool {
+        self.is_active(action, |button| match button {
+            InputButton::Key(key) => self.keyboard_input.just_released(key),
+            InputButton::Mouse(button) => self.mouse_button_input.just_released(button),
+            InputButton::Gamepad(button) => {
+                self.any_gamepad(button, |b| self.gamepad_button_input.just_released(b))
+            }
+        })
+    }
+
+    fn any_gamepad(
+        &self,
+        button_type: GamepadButtonType,
+        is_button_active: impl Fn(GamepadButton) -> bool,
+    ) -> bool {
+        self.gamepads
+            .iter()
+            .any(|gamepad| is_button_active(GamepadButton::new(gamepad, button_type)))
+    }
+
+    fn is_active(&self, action: Action, is_button_active: impl Fn(InputButton) -> bool) -> bool {
+        let Some(bindings) = self.input_map.0.get(&action) else {
+            return false;
+        };
+        bindings.iter().any(|binding| {
+            self.input_map
+                .is_binding_active(binding, &is_button_active, |modifier| {
+                    self.keyboard_input.any_pressed(modifier.keys())
+                })
+        })
+    }
+}
+
+/// Combined position of a stick on all connected gamepads, with y pointing up.
+pub fn gamepad_stick(
+    gamepads: &Gamepads,
+    gamepad_axes: &Axis<GamepadAxis>,
+    x_axis: GamepadAxisType,
+    y_axis: GamepadAxisType,
+) -> Vec2 {
+    let axis = |gamepad, axis_type| {
+        gamepad_axes
+            .get(GamepadAxis::new(gamepad, axis_type))
+            .unwrap_or(0.)
+    };
+    gamepads
+        .iter()
+        .map(|gamepad| Vec2::new(axis(gamepad, x_axis), axis(gamepad, y_axis)))
+        .sum::<Vec2>()
+        .clamp_length_max(1.)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn is_action_active(
+        input_map: &InputMap,
+        action: Action,
+        button: InputButton,
+        modifiers: &[Modifier],
+    ) -> bool {
+        input_map.0[&action].iter().any(|binding| {
+            input_map.is_binding_active(binding, |b| b == button, |m| modifiers.contains(&m))
+        })
+    }
+
+    #[test]
+    fn test_more_specific_binding_takes_precedence() {
+        let input_map = InputMap::default();
+        let s = InputButton::Key(KeyCode::KeyS);
+
+        assert!(is_action_active(&input_map, Action::StepFrame, s, &[]));
+        assert!(!is_action_active(&input_map, Action::SaveStamp, s, &[]));
+
+        let control = [Modifier::Control];
+        assert!(!is_action_active(
+            &input_map,
+            Action::StepFrame,
+            s,
+            &control
+        ));
+        assert!(is_action_active(&input_map, Action::SaveStamp, s, &control));
+
+        // Modifiers that no binding on the button uses don't block it
+        let left = InputButton::Mouse(MouseButton::Left);
+        assert!(is_action_active(&input_map, Action::Draw, left, &control));
+        assert!(!is_action_active(
+            &input_map,
+            Action::Draw,
+            left,
+            &[Modifier::Alt]
+        ));
+    }
+
+    #[test]
+    fn test_apply_config() {
+        let mut input_map = InputMap::default();
+        input_map
+            .apply_config("{ Pan: [(button: Mouse(Right))], SelectMaterial(Water): [] }")
+            .unwrap();
+
+        assert_eq!(
+            input_map.0[&Action::Pan],
+            vec![Binding::mouse(MouseButton::Right)]
+        );
+        assert!(input_map.0[&Action::SelectMaterial(Material::Water)].is_empty());
+        assert_eq!(
+            input_map.0[&Action::Draw],
+            InputMap::default().0[&Action::Draw]
+        );
+
+        assert!(input_map
+            .apply_config("{ Pan: [(button: Key(NotAKey))] }")
+            .is_err());
+    }
+}
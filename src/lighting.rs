@@ -0,0 +1,505 @@
+use bevy::{
+    app::{App, Plugin, PostUpdate, Startup, Update},
+    asset::{Asset, Assets, Handle},
+    ecs::{
+        change_detection::DetectChanges,
+        component::Component,
+        query::With,
+        schedule::IntoSystemConfigs,
+        system::{Commands, Query, Res, ResMut, Resource},
+    },
+    math::{primitives::Rectangle, IVec2, UVec2, Vec3},
+    reflect::TypePath,
+    render::{
+        color::Color,
+        mesh::{Mesh, MeshVertexBufferLayout},
+        render_asset::RenderAssetUsages,
+        render_resource::{
+            AsBindGroup, BlendComponent, BlendFactor, BlendOperation, BlendState, Extent3d,
+            RenderPipelineDescriptor, ShaderRef, SpecializedMeshPipelineError, TextureDimension,
+            TextureFormat,
+        },
+        texture::{Image, ImageSampler},
+        view::Visibility,
+    },
+    sprite::{Material2d, Material2dKey, Material2dPlugin, MaterialMesh2dBundle, Mesh2dHandle},
+    transform::components::Transform,
+    utils::{default, HashMap, HashSet},
+};
+use enum_map::EnumMap;
+use ndarray::Array2;
+
+use crate::{
+    chunk::{Chunk, ChunkData},
+    consts::CHUNK_SIZE,
+    falling_sand::{ChunkPosition, FallingSandSettings},
+    input_map::{Action, ActionInput},
+    material::{Material, MaterialEmission},
+    util::chunk_neighbors,
+    visible_chunks::{update_visible_chunks, VisibleChunks},
+};
+
+pub struct LightingPlugin;
+
+impl Plugin for LightingPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(Material2dPlugin::<LightMapMaterial>::default())
+            .init_resource::<LightingSettings>()
+            .init_resource::<ChunkEmission>()
+            .init_resource::<LightMap>()
+            .add_systems(Startup, spawn_light_map_layers)
+            .add_systems(Update, (cycle_ambient_light, show_light_maps).chain())
+            .add_systems(PostUpdate, update_light_maps.after(update_visible_chunks));
+    }
+}
+
+/// Ambient light levels selectable with the cycle ambient light key, from day to night.
+pub const AMBIENT_LIGHT_STEPS: [f32; 3] = [1., 0.4, 0.08];
+
+#[derive(Resource)]
+pub struct LightingSettings {
+    /// Light that reaches every tile. At 1 the scene isn't lit and the light maps are hidden.
+    pub ambient: f32,
+}
+
+impl Default for LightingSettings {
+    fn default() -> Self {
+        Self {
+            ambient: AMBIENT_LIGHT_STEPS[0],
+        }
+    }
+}
+
+impl LightingSettings {
+    fn enabled(&self) -> bool {
+        self.ambient < 1.
+    }
+}
+
+/// Width and height in tiles of a light map cell.
+const LIGHT_CELL_SIZE: i32 = 4;
+const LIGHT_MAP_SIZE: i32 = CHUNK_SIZE / LIGHT_CELL_SIZE;
+/// Distance in cells that light from an emissive cell reaches. At most `LIGHT_MAP_SIZE`, so
+/// only the neighboring chunks light a chunk.
+const LIGHT_RADIUS: i32 = 6;
+
+/// Draws the light map over the chunk sprites, either multiplying them by it or adding it to
+/// them.
+#[derive(Asset, TypePath, AsBindGroup, Clone)]
+#[bind_group_data(LightMapMaterialKey)]
+struct LightMapMaterial {
+    #[texture(0)]
+    #[sampler(1)]
+    light_map: Handle<Image>,
+    additive: bool,
+}
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct LightMapMaterialKey {
+    additive: bool,
+}
+
+impl From<&LightMapMaterial> for LightMapMaterialKey {
+    fn from(material: &LightMapMaterial) -> Self {
+        LightMapMaterialKey {
+            additive: material.additive,
+        }
+    }
+}
+
+impl Material2d for LightMapMaterial {
+    fn fragment_shader() -> ShaderRef {
+        "shaders/light_map.wgsl".into()
+    }
+
+    fn specialize(
+        descriptor: &mut RenderPipelineDescriptor,
+        _layout: &MeshVertexBufferLayout,
+        key: Material2dKey<Self>,
+    ) -> Result<(), SpecializedMeshPipelineError> {
+        let color = if key.bind_group_data.additive {
+            BlendComponent {
+                src_factor: BlendFactor::One,
+                dst_factor: BlendFactor::One,
+                operation: BlendOperation::Add,
+            }
+        } else {
+            BlendComponent {
+                src_factor: BlendFactor::Dst,
+                dst_factor: BlendFactor::Zero,
+                operation: BlendOperation::Add,
+            }
+        };
+        let blend = BlendState {
+            color,
+            alpha: BlendComponent::OVER,
+        };
+        if let Some(fragment) = descriptor.fragment.as_mut() {
+            for target in fragment.targets.iter_mut().flatten() {
+                target.blend = Some(blend);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Part of the emitted light that is added on top of the lit chunk sprites, so emissive
+/// materials glow brighter than their own color.
+const GLOW_STRENGTH: f32 = 0.5;
+
+/// Layer of the light maps of the visible chunks, drawn as a single quad over the chunk atlas
+/// mesh. The light layer multiplies the chunk sprites by the ambient and emitted light, the glow
+/// layer adds part of the emitted light on top.
+#[derive(Component)]
+struct LightMapLayer {
+    glow: bool,
+    image: Handle<Image>,
+}
+
+/// Average emission of the particles in every light map cell of every chunk, indexed by cell
+/// (x, y).
+#[derive(Resource, Default)]
+struct ChunkEmission(HashMap<IVec2, Array2<Vec3>>);
+
+/// Light of every cell of the visible chunks, written into the images of the light map layers.
+#[derive(Resource, Default)]
+struct LightMap {
+    visible_chunks: VisibleChunks,
+    /// Light emitted onto the cells of the visible chunks, without the ambient light.
+    light: HashMap<IVec2, Array2<Vec3>>,
+}
+
+fn cycle_ambient_light(action_input: ActionInput, mut lighting_settings: ResMut<LightingSettings>) {
+    if !action_input.just_pressed(Action::CycleAmbientLight) {
+        return;
+    }
+
+    let step = AMBIENT_LIGHT_STEPS
+        .iter()
+        .position(|ambient| *ambient == lighting_settings.ambient)
+        .map_or(0, |step| (step + 1) % AMBIENT_LIGHT_STEPS.len());
+    lighting_settings.ambient = AMBIENT_LIGHT_STEPS[step];
+}
+
+fn create_light_map_image(size: UVec2) -> Image {
+    let mut light_map_image = Image::new_fill(
+        Extent3d {
+            width: size.x.max(1),
+            height: size.y.max(1),
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        &Color::BLACK.as_rgba_u8(),
+        TextureFormat::Rgba8Unorm,
+        RenderAssetUsages::MAIN_WORLD | RenderAssetUsages::RENDER_WORLD,
+    );
+    // Smooth out the light between cells
+    light_map_image.sampler = ImageSampler::linear();
+    light_map_image
+}
+
+fn spawn_light_map_layers(
+    mut commands: Commands,
+    lighting_settings: Res<LightingSettings>,
+    mut images: ResMut<Assets<Image>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<LightMapMaterial>>,
+) {
+    // Scaled to the visible chunks by `update_light_maps`
+    let mesh: Mesh2dHandle = meshes.add(Rectangle::new(1., 1.)).into();
+    for (glow, z) in [(false, 1.), (true, 1.1)] {
+        let image = images.add(create_light_map_image(UVec2::ONE));
+        commands.spawn((
+            MaterialMesh2dBundle {
+                mesh: mesh.clone(),
+                material: materials.add(LightMapMaterial {
+                    light_map: image.clone(),
+                    additive: glow,
+                }),
+                transform: Transform::from_xyz(0., 0., z),
+                visibility: light_map_visibility(&lighting_settings),
+                ..default()
+            },
+            LightMapLayer { glow, image },
+        ));
+    }
+}
+
+fn light_map_visibility(lighting_settings: &LightingSettings) -> Visibility {
+    if lighting_settings.enabled() {
+        Visibility::Inherited
+    } else {
+        Visibility::Hidden
+    }
+}
+
+fn show_light_maps(
+    lighting_settings: Res<LightingSettings>,
+    mut light_map_query: Query<&mut Visibility, With<LightMapLayer>>,
+) {
+    if !lighting_settings.is_changed() {
+        return;
+    }
+
+    for mut visibility in light_map_query.iter_mut() {
+        *visibility = light_map_visibility(&lighting_settings);
+    }
+}
+
+/// Average emission of the particles in every light map cell of a chunk.
+fn chunk_emission(
+    chunk_data: &ChunkData,
+    material_emission: &EnumMap<Material, Vec3>,
+) -> Array2<Vec3> {
+    let mut emission = Array2::from_elem(
+        (LIGHT_MAP_SIZE as usize, LIGHT_MAP_SIZE as usize),
+        Vec3::ZERO,
+    );
+    let cell_size = LIGHT_CELL_SIZE as usize;
+    for ((x, y), particle) in chunk_data.particles().array().indexed_iter() {
+        emission[(x / cell_size, y / cell_size)] += material_emission[particle.material()];
+    }
+    emission.mapv_inplace(|cell| cell / (cell_size * cell_size) as f32);
+    emission
+}
+
+/// Weight of light from a cell `distance` cells away along one axis.
+fn light_falloff(distance: i32) -> f32 {
+    1. - distance.abs() as f32 / (LIGHT_RADIUS + 1) as f32
+}
+
+/// Light emitted onto every cell of the chunk at `position` by the emissive cells around it,
+/// spread by a separable falloff.
+fn chunk_light(position: IVec2, emission: &HashMap<IVec2, Array2<Vec3>>) -> Array2<Vec3> {
+    let emission_at = |cell: IVec2| {
+        let chunk_offset = IVec2::new(
+            cell.x.div_euclid(LIGHT_MAP_SIZE),
+            cell.y.div_euclid(LIGHT_MAP_SIZE),
+        );
+        let local_cell = cell - chunk_offset * LIGHT_MAP_SIZE;
+        emission
+            .get(&(position + chunk_offset))
+            .map_or(Vec3::ZERO, |cells| {
+                cells[(local_cell.x as usize, local_cell.y as usize)]
+            })
+    };
+
+    // Spread horizontally over the rows that can reach the chunk, then vertically
+    let rows = -LIGHT_RADIUS..LIGHT_MAP_SIZE + LIGHT_RADIUS;
+    let horizontal = Array2::from_shape_fn((LIGHT_MAP_SIZE as usize, rows.len()), |(x, row)| {
+        let cell = IVec2::new(x as i32, row as i32 - LIGHT_RADIUS);
+        (-LIGHT_RADIUS..=LIGHT_RADIUS)
+            .map(|dx| emission_at(cell + IVec2::new(dx, 0)) * light_falloff(dx))
+            .sum::<Vec3>()
+    });
+    Array2::from_shape_fn(
+        (LIGHT_MAP_SIZE as usize, LIGHT_MAP_SIZE as usize),
+        |(x, y)| {
+            (-LIGHT_RADIUS..=LIGHT_RADIUS)
+                .map(|dy| {
+                    horizontal[(x, (y as i32 + LIGHT_RADIUS + dy) as usize)] * light_falloff(dy)
+                })
+                .sum::<Vec3>()
+        },
+    )
+}
+
+/// RGBA pixels of a light map layer covering `visible_chunks`, starting at the top row. Each
+/// cell is `cell_light` of the light emitted onto it, chunks without light are black.
+fn light_map_texture_data(
+    visible_chunks: &VisibleChunks,
+    light: &HashMap<IVec2, Array2<Vec3>>,
+    cell_light: impl Fn(Vec3) -> Vec3,
+) -> Vec<u8> {
+    let min_cell = visible_chunks.min * LIGHT_MAP_SIZE;
+    let max_cell = (visible_chunks.max + IVec2::ONE) * LIGHT_MAP_SIZE - IVec2::ONE;
+    (min_cell.y..=max_cell.y)
+        .rev()
+        .flat_map(|y| (min_cell.x..=max_cell.x).map(move |x| IVec2::new(x, y)))
+        .flat_map(|cell| {
+            let chunk = IVec2::new(
+                cell.x.div_euclid(LIGHT_MAP_SIZE),
+                cell.y.div_euclid(LIGHT_MAP_SIZE),
+            );
+            let local_cell = cell - chunk * LIGHT_MAP_SIZE;
+            let emitted = light.get(&chunk).map_or(Vec3::ZERO, |cells| {
+                cells[(local_cell.x as usize, local_cell.y as usize)]
+            });
+            let color = cell_light(emitted).clamp(Vec3::ZERO, Vec3::ONE) * u8::MAX as f32;
+            [color.x as u8, color.y as u8, color.z as u8, u8::MAX]
+        })
+        .collect()
+}
+
+/// Recompute the emission of dirty chunks, and the light of the visible chunks that it reaches.
+#[allow(clippy::too_many_arguments)]
+fn update_light_maps(
+    lighting_settings: Res<LightingSettings>,
+    material_emission: Res<MaterialEmission>,
+    falling_sand_settings: Res<FallingSandSettings>,
+    visible_chunks: Res<VisibleChunks>,
+    chunk_query: Query<(&Chunk, &ChunkPosition)>,
+    mut emission: ResMut<ChunkEmission>,
+    mut light_map: ResMut<LightMap>,
+    mut layer_query: Query<(&LightMapLayer, &mut Transform)>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    if !lighting_settings.enabled() {
+        return;
+    }
+    // Emission isn't kept up to date while lighting is disabled
+    let update_all = lighting_settings.is_changed() || material_emission.is_changed();
+    let visible_chunks_changed = light_map.visible_chunks != *visible_chunks;
+    let material_emission = EnumMap::from_fn(|material| {
+        let [r, g, b, _] = material_emission[material].as_linear_rgba_f32();
+        Vec3::new(r, g, b)
+    });
+
+    let mut changed_positions = HashSet::new();
+    for (chunk, position) in chunk_query.iter() {
+        let chunk_data = chunk.read().unwrap();
+        let known = emission.0.contains_key(&position.0);
+        if known && !update_all && !chunk_data.is_dirty() {
+            continue;
+        }
+
+        let cell_emission = chunk_emission(&chunk_data, &material_emission);
+        if emission.0.get(&position.0) != Some(&cell_emission) {
+            changed_positions.insert(position.0);
+            emission.0.insert(position.0, cell_emission);
+        }
+    }
+
+    // Only chunks next to a chunk whose emission changed are reached by its light
+    let reached = |position: IVec2| {
+        changed_positions.contains(&position)
+            || chunk_neighbors(position)
+                .iter()
+                .any(|neighbor| changed_positions.contains(neighbor))
+    };
+    let mut light = std::mem::take(&mut light_map.light);
+    if update_all {
+        light.clear();
+    }
+    light.retain(|position, _| visible_chunks.contains(*position));
+    let mut light_changed = update_all || visible_chunks_changed;
+    let visible_positions = (visible_chunks.min.y..=visible_chunks.max.y)
+        .flat_map(|y| (visible_chunks.min.x..=visible_chunks.max.x).map(move |x| IVec2::new(x, y)));
+    for position in visible_positions {
+        if !light.contains_key(&position) || reached(position) {
+            light.insert(position, chunk_light(position, &emission.0));
+            light_changed = true;
+        }
+    }
+    light_map.light = light;
+    light_map.visible_chunks = *visible_chunks;
+    if !light_changed {
+        return;
+    }
+
+    let chunk_world_size = (CHUNK_SIZE * falling_sand_settings.tile_size as i32) as f32;
+    let chunk_count = visible_chunks.max - visible_chunks.min + IVec2::ONE;
+    if chunk_count.cmple(IVec2::ZERO).any() {
+        return;
+    }
+    let size = (chunk_count * LIGHT_MAP_SIZE).as_uvec2();
+    let ambient = lighting_settings.ambient;
+    for (layer, mut transform) in layer_query.iter_mut() {
+        let center = (visible_chunks.min + visible_chunks.max).as_vec2() / 2. * chunk_world_size;
+        transform.translation = center.extend(transform.translation.z);
+        transform.scale = (chunk_count.as_vec2() * chunk_world_size).extend(1.);
+
+        let Some(image) = images.get_mut(&layer.image) else {
+            continue;
+        };
+        if image.size() != size {
+            *image = create_light_map_image(size);
+        }
+        image.data = if layer.glow {
+            light_map_texture_data(visible_chunks.as_ref(), &light_map.light, |emitted| {
+                emitted * GLOW_STRENGTH
+            })
+        } else {
+            light_map_texture_data(visible_chunks.as_ref(), &light_map.light, |emitted| {
+                emitted + Vec3::splat(ambient)
+            })
+        };
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+
+    fn fire_emission() -> EnumMap<Material, Vec3> {
+        EnumMap::from_fn(|material| match material {
+            Material::Fire => Vec3::ONE,
+            _ => Vec3::ZERO,
+        })
+    }
+
+    #[test]
+    fn test_chunk_emission_averages_cells() {
+        let chunk = Chunk::new_with_material(
+            (CHUNK_SIZE as usize, CHUNK_SIZE as usize),
+            Material::Air,
+            StdRng::seed_from_u64(0),
+        );
+        let mut chunk_data = chunk.write().unwrap();
+        for x in 0..LIGHT_CELL_SIZE {
+            chunk_data.set_particle_material(IVec2::new(x, 0), Material::Fire);
+        }
+
+        let emission = chunk_emission(&chunk_data, &fire_emission());
+        assert_eq!(emission[(0, 0)], Vec3::splat(1. / LIGHT_CELL_SIZE as f32));
+        assert_eq!(emission[(1, 0)], Vec3::ZERO);
+    }
+
+    #[test]
+    fn test_chunk_light_falls_off_across_chunks() {
+        let mut fire = Array2::from_elem(
+            (LIGHT_MAP_SIZE as usize, LIGHT_MAP_SIZE as usize),
+            Vec3::ZERO,
+        );
+        // Fire in the right column of the chunk left of the lit chunk
+        fire[(LIGHT_MAP_SIZE as usize - 1, 0)] = Vec3::ONE;
+        let dark = Array2::from_elem(fire.dim(), Vec3::ZERO);
+        let emission = HashMap::from([(IVec2::new(-1, 0), fire), (IVec2::ZERO, dark)]);
+
+        let light = chunk_light(IVec2::ZERO, &emission);
+        assert_eq!(light[(0, 0)], Vec3::splat(light_falloff(1)));
+        assert!(light[(LIGHT_RADIUS as usize - 1, 0)].x > 0.);
+        assert_eq!(light[(LIGHT_RADIUS as usize, 0)], Vec3::ZERO);
+        assert_eq!(light[(0, LIGHT_RADIUS as usize + 1)], Vec3::ZERO);
+    }
+
+    #[test]
+    fn test_light_map_texture_layout() {
+        let visible_chunks = VisibleChunks {
+            min: IVec2::new(-1, 0),
+            max: IVec2::new(0, 1),
+        };
+        let mut lit = Array2::from_elem(
+            (LIGHT_MAP_SIZE as usize, LIGHT_MAP_SIZE as usize),
+            Vec3::ZERO,
+        );
+        lit[(0, 0)] = Vec3::ONE;
+        // The bottom left cell of the bottom right chunk
+        let light = HashMap::from([(IVec2::ZERO, lit)]);
+
+        let data = light_map_texture_data(&visible_chunks, &light, |emitted| {
+            emitted * 0.5 + Vec3::splat(0.75)
+        });
+        let width = 2 * LIGHT_MAP_SIZE as usize;
+        assert_eq!(data.len(), width * width * 4);
+        let pixel = |x: usize, y: usize| &data[(y * width + x) * 4..(y * width + x + 1) * 4];
+        // Emitted light above 1 is clamped
+        assert_eq!(
+            pixel(LIGHT_MAP_SIZE as usize, width - 1),
+            [255, 255, 255, 255]
+        );
+        assert_eq!(pixel(0, 0), [191, 191, 191, 255]);
+    }
+}
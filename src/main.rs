@@ -1,53 +1,125 @@
 #[macro_use]
 extern crate enum_map;
 
+use cli::Args;
 use cursor_world_position::CursorWorldPositionPlugin;
+use debug_overlay::DebugOverlayPlugin;
 use draw_tool::DrawToolPlugin;
+use emitters::EmitterPlugin;
+use export::{ExportPlugin, ExportSettings};
 
 use hovering_ui::HoveringUiPlugin;
+use image_import::{ImageImportPlugin, ImageImportSettings};
+use input_map::InputMapPlugin;
+use lighting::LightingPlugin;
 use pan_zoom_camera::{DragState, PanZoomCameraPlugin};
+use particle_inspector::ParticleInspectorPlugin;
+#[cfg(not(target_arch = "wasm32"))]
+use recording::{RecordingFormat, RecordingPlugin, RecordingSettings};
 
-use crate::falling_sand::FallingSandPlugin;
+use crate::falling_sand::{ChunkRenderer, FallingSandPlugin, FallingSandSettings};
 use bevy::prelude::*;
+use selection::SelectionPlugin;
+use stamps::{StampPlugin, StampSettings};
+use stats::StatsPlugin;
 use time_control::TimeControlPlugin;
 
 mod active_chunks;
 mod chunk;
 mod chunk_neighborhood_view;
+mod cli;
 mod consts;
 mod cursor_world_position;
+mod debug_overlay;
 mod draw_tool;
+mod emitters;
+mod export;
 mod fall;
 mod falling_sand;
 mod falling_sand_grid;
 mod fire;
 mod flow;
 mod hovering_ui;
+mod image_import;
+mod input_map;
+mod lighting;
 mod material;
 mod pan_zoom_camera;
 mod particle_attributes;
 mod particle_grid;
+mod particle_inspector;
+mod particle_region;
 mod process_chunks;
 mod reactions;
+// Recordings are written to files by a writer thread, which the web build has neither of
+#[cfg(not(target_arch = "wasm32"))]
+mod recording;
 mod render;
+mod selection;
 mod spatial_store;
+mod stamps;
+mod stats;
 mod time_control;
+mod ui;
 mod util;
+mod visible_chunks;
 
 fn main() {
+    let args = Args::from_env();
     let mut app = App::new();
 
     app.add_plugins((
         DefaultPlugins.set(ImagePlugin::default_nearest()),
+        InputMapPlugin,
         CursorWorldPositionPlugin,
         PanZoomCameraPlugin,
-        FallingSandPlugin::default(),
+        FallingSandPlugin {
+            settings: FallingSandSettings {
+                renderer: args.cpu_render.then_some(ChunkRenderer::Cpu),
+                distant_tick_interval: args.distant_tick_interval.unwrap_or(1),
+                ..default()
+            },
+        },
         HoveringUiPlugin,
         DrawToolPlugin,
         TimeControlPlugin,
+        ParticleInspectorPlugin,
+        StatsPlugin,
+        DebugOverlayPlugin,
+        SelectionPlugin,
+        StampPlugin {
+            settings: args
+                .stamp_dir
+                .map(|directory| StampSettings { directory })
+                .unwrap_or_default(),
+        },
+        EmitterPlugin,
     ))
-    .add_systems(Startup, setup)
-    .run();
+    .add_plugins(LightingPlugin)
+    .add_plugins(ImageImportPlugin {
+        settings: ImageImportSettings { world: args.import },
+    })
+    .add_plugins(ExportPlugin {
+        settings: ExportSettings {
+            scale: args.export_scale.unwrap_or(1),
+            ..default()
+        },
+    })
+    .add_systems(Startup, setup);
+
+    #[cfg(not(target_arch = "wasm32"))]
+    app.add_plugins(RecordingPlugin {
+        settings: RecordingSettings {
+            format: if args.record_png {
+                RecordingFormat::Png
+            } else {
+                RecordingFormat::Gif
+            },
+            ..default()
+        },
+    });
+
+    app.run();
 }
 
 fn setup(mut commands: Commands) {
@@ -4,6 +4,7 @@ use bevy::prelude::*;
 
 use bytemuck::{Contiguous, NoUninit};
 use enum_map::EnumMap;
+use serde::{Deserialize, Serialize};
 
 use crate::consts::INITIAL_MATERIAL;
 
@@ -16,6 +17,8 @@ impl Plugin for MaterialPlugin {
             material_color[INITIAL_MATERIAL].as_rgba_linear(),
         ));
         app.insert_resource(material_color)
+            .init_resource::<MaterialColorVariation>()
+            .init_resource::<MaterialEmission>()
             .init_resource::<MaterialDensities>()
             .init_resource::<MaterialStates>()
             .init_resource::<MaterialFlowing>()
@@ -23,7 +26,9 @@ impl Plugin for MaterialPlugin {
     }
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Enum, NoUninit, Reflect, Hash)]
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, Enum, NoUninit, Reflect, Hash, Serialize, Deserialize,
+)]
 #[repr(u16)]
 pub enum Material {
     Air = 0,
@@ -201,6 +206,41 @@ impl Default for MaterialColor {
     }
 }
 
+/// Second color of each material's gradient. Particles get a color between their
+/// `MaterialColor` and this color, picked by their color seed.
+#[derive(Resource, Deref)]
+pub struct MaterialColorVariation(pub EnumMap<Material, Color>);
+
+impl Default for MaterialColorVariation {
+    fn default() -> Self {
+        MaterialColorVariation(enum_map! {
+            Material::Air => Color::rgb_u8(240, 248, 255u8),
+            Material::Bedrock => Color::rgb_u8(72, 70, 68u8),
+            Material::Sand => Color::rgb_u8(166, 148, 98u8),
+            Material::Water => Color::rgb_u8(36, 118, 172u8),
+            Material::Fire => Color::rgb_u8(255, 96, 0u8),
+            Material::Smoke => Color::rgb_u8(132, 132, 132u8),
+            Material::Wood => Color::rgb_u8(128, 64, 34u8),
+            Material::Steam => Color::rgb_u8(246, 246, 246u8),
+            Material::Oil => Color::rgb_u8(62, 56, 10u8),
+            Material::Plant => Color::rgb_u8(34, 128, 20u8),
+        })
+    }
+}
+
+/// Light given off by each material, as a linear color. Most materials don't glow.
+#[derive(Resource, Deref)]
+pub struct MaterialEmission(pub EnumMap<Material, Color>);
+
+impl Default for MaterialEmission {
+    fn default() -> Self {
+        MaterialEmission(EnumMap::from_fn(|material| match material {
+            Material::Fire => Color::rgb_linear(1.0, 0.45, 0.12),
+            _ => Color::BLACK,
+        }))
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Reaction {
     probability: u32,
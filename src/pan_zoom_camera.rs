@@ -1,11 +1,23 @@
-use bevy::{input::mouse::MouseWheel, prelude::*, render::camera::Camera, window::PrimaryWindow};
+use bevy::{
+    input::{
+        gamepad::{GamepadAxis, GamepadAxisType, Gamepads},
+        mouse::MouseWheel,
+    },
+    prelude::*,
+    render::camera::Camera,
+    window::PrimaryWindow,
+};
+
+use crate::input_map::{gamepad_stick, Action, ActionInput};
 
 pub struct PanZoomCameraPlugin;
 
 impl Plugin for PanZoomCameraPlugin {
     fn build(&self, app: &mut App) {
-        app.init_resource::<CameraSettings>()
-            .add_systems(Update, (camera_zoom, move_camera_mouse));
+        app.init_resource::<CameraSettings>().add_systems(
+            Update,
+            (camera_zoom, move_camera_mouse, move_camera_gamepad),
+        );
     }
 }
 
@@ -14,6 +26,8 @@ pub struct CameraSettings {
     pub zoom_speed: f32,
     pub min_zoom: f32,
     pub max_zoom: f32,
+    /// Camera speed at full stick deflection, in logical pixels per second.
+    pub gamepad_pan_speed: f32,
 }
 
 impl Default for CameraSettings {
@@ -22,6 +36,7 @@ impl Default for CameraSettings {
             zoom_speed: 0.1,
             min_zoom: 0.01,
             max_zoom: 10.0,
+            gamepad_pan_speed: 800.0,
         }
     }
 }
@@ -29,11 +44,14 @@ impl Default for CameraSettings {
 fn camera_zoom(
     mut camera_query: Query<(&mut Transform, &mut OrthographicProjection)>,
     mut mouse_wheel_events: EventReader<MouseWheel>,
-    keyboard_input: Res<ButtonInput<KeyCode>>,
+    action_input: ActionInput,
     window_query: Query<&Window, With<PrimaryWindow>>,
     camera_settings: Res<CameraSettings>,
 ) {
-    if keyboard_input.pressed(KeyCode::ControlLeft) {
+    // The mouse wheel changes brush settings while their modifier is held
+    if action_input.pressed(Action::BrushSizeModifier)
+        || action_input.pressed(Action::SprayDensityModifier)
+    {
         return;
     }
     let Ok(primary_window) = window_query.get_single() else {
@@ -74,7 +92,7 @@ pub struct DragState {
 }
 
 pub fn move_camera_mouse(
-    mouse_button_input: Res<ButtonInput<MouseButton>>,
+    action_input: ActionInput,
     windows: Query<&Window>,
     mut camera_query: Query<
         (&mut Transform, &mut OrthographicProjection, &mut DragState),
@@ -83,13 +101,13 @@ pub fn move_camera_mouse(
 ) {
     if let Ok(window) = windows.get_single() {
         for (mut transform, ortho, mut state) in camera_query.iter_mut() {
-            if mouse_button_input.just_pressed(MouseButton::Middle) {
+            if action_input.just_pressed(Action::Pan) {
                 if let Some(cursor_pos) = window.cursor_position() {
                     state.drag_start = Some((cursor_pos, transform.translation));
                 }
             }
 
-            if mouse_button_input.just_released(MouseButton::Middle) {
+            if action_input.just_released(Action::Pan) {
                 state.drag_start = None;
             }
 
@@ -105,3 +123,27 @@ pub fn move_camera_mouse(
         }
     }
 }
+
+pub fn move_camera_gamepad(
+    gamepads: Res<Gamepads>,
+    gamepad_axes: Res<Axis<GamepadAxis>>,
+    time: Res<Time>,
+    camera_settings: Res<CameraSettings>,
+    mut camera_query: Query<(&mut Transform, &OrthographicProjection), With<Camera>>,
+) {
+    let stick = gamepad_stick(
+        &gamepads,
+        &gamepad_axes,
+        GamepadAxisType::RightStickX,
+        GamepadAxisType::RightStickY,
+    );
+    if stick == Vec2::ZERO {
+        return;
+    }
+
+    for (mut transform, ortho) in camera_query.iter_mut() {
+        let movement =
+            stick * camera_settings.gamepad_pan_speed * ortho.scale * time.delta_seconds();
+        transform.translation += movement.extend(0.);
+    }
+}
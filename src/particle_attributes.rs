@@ -2,7 +2,7 @@ use bevy::math::IVec2;
 
 use crate::{
     chunk::ChunkData,
-    particle_grid::{Particle, ParticleAttributeStore},
+    particle_grid::{Particle, ParticleAttributeStore, ParticleId},
 };
 
 macro_rules! define_attributes_and_swap {
@@ -18,6 +18,22 @@ macro_rules! define_attributes_and_swap {
                     $($attr: ParticleAttributeStore::new(size),)*
                 }
             }
+
+            pub fn get_values(&self, id: ParticleId) -> ParticleAttributeValues {
+                ParticleAttributeValues {
+                    $($attr: self.$attr.get(id).unwrap().clone(),)*
+                }
+            }
+
+            pub fn set_values(&mut self, id: ParticleId, values: ParticleAttributeValues) {
+                $(self.$attr.set(id, values.$attr);)*
+            }
+        }
+
+        /// The attributes of a single particle.
+        #[derive(Debug, Clone, Default, PartialEq)]
+        pub struct ParticleAttributeValues {
+            $(pub $attr: $type,)*
         }
 
         pub fn swap_particles_between_chunks(
@@ -38,6 +54,10 @@ macro_rules! define_attributes_and_swap {
                 particle_a.set_material(particle_b_material);
                 particle_b.set_material(particle_a_material);
 
+                let particle_a_color_seed = particle_a.color_seed();
+                particle_a.set_color_seed(particle_b.color_seed());
+                particle_b.set_color_seed(particle_a_color_seed);
+
                 let particle_a_id = particle_a.id();
                 let particle_b_id = particle_b.id();
 
@@ -29,6 +29,8 @@ bitfield! {
     u16;
     pub from into ParticleId, id, set_id: 9, 0;
     pub from into Material, material, set_material: 19, 10;
+    /// Picks the particle's color from its material's color gradient.
+    pub u8, color_seed, set_color_seed: 27, 20;
     pub dirty, set_dirty: 31;
 }
 
@@ -97,3 +99,24 @@ impl<T: Default + Clone> ParticleAttributeStore<T> {
         self.data.iter_mut()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_color_seed_bits() {
+        let mut particle = Particle::new(Material::Plant, 1023);
+        particle.set_dirty(true);
+        particle.set_color_seed(u8::MAX);
+
+        assert_eq!(particle.color_seed(), u8::MAX);
+        assert_eq!(particle.material(), Material::Plant);
+        assert_eq!(u16::from(particle.id()), 1023);
+        assert!(particle.dirty());
+
+        particle.set_color_seed(0);
+        assert_eq!(u32::from(particle) >> 20 & 0xFF, 0);
+        assert_eq!(particle.material(), Material::Plant);
+    }
+}
@@ -0,0 +1,161 @@
+use bevy::{
+    app::{App, Plugin, Startup, Update},
+    asset::AssetServer,
+    ecs::{
+        component::Component,
+        query::{Has, With},
+        schedule::{common_conditions::resource_equals, IntoSystemConfigs},
+        system::{Commands, Query, Res, ResMut, Resource},
+    },
+    hierarchy::BuildChildren,
+    math::IVec2,
+    prelude::{default, Color},
+    text::Text,
+    ui::{
+        node_bundles::{NodeBundle, TextBundle},
+        Display, PositionType, Style, UiRect, Val,
+    },
+    window::{PrimaryWindow, Window},
+};
+
+use crate::{
+    active_chunks::ChunkActive,
+    chunk::Chunk,
+    consts::CHUNK_SIZE,
+    draw_tool::CursorTilePosition,
+    falling_sand::ChunkPositions,
+    input_map::{Action, ActionInput},
+    ui,
+    util::{positive_mod, tile_pos_to_chunk_pos},
+};
+
+pub struct ParticleInspectorPlugin;
+
+impl Plugin for ParticleInspectorPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ParticleInspector>()
+            .add_systems(Startup, setup_ui)
+            .add_systems(
+                Update,
+                (
+                    toggle_particle_inspector,
+                    update_particle_inspector.run_if(resource_equals(ParticleInspector(true))),
+                    hide_particle_inspector.run_if(resource_equals(ParticleInspector(false))),
+                )
+                    .chain(),
+            );
+    }
+}
+
+#[derive(Resource, Default, PartialEq)]
+struct ParticleInspector(bool);
+
+/// Offset of the inspector panel from the cursor, so it doesn't cover the inspected particle.
+const PANEL_CURSOR_OFFSET: f32 = 16.;
+
+#[derive(Component)]
+struct ParticleInspectorPanel;
+
+#[derive(Component)]
+struct ParticleInspectorText;
+
+fn setup_ui(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let font = asset_server.load(ui::FONT_PATH);
+
+    commands
+        .spawn((
+            ParticleInspectorPanel,
+            NodeBundle {
+                style: Style {
+                    display: Display::None,
+                    position_type: PositionType::Absolute,
+                    border: UiRect::all(Val::Px(2.0)),
+                    padding: UiRect::all(Val::Px(2.0)),
+                    ..default()
+                },
+                border_color: ui::border_color(),
+                background_color: Color::WHITE.into(),
+                ..default()
+            },
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                ParticleInspectorText,
+                TextBundle::from_section("", ui::text_style(&font)),
+            ));
+        });
+}
+
+fn toggle_particle_inspector(
+    action_input: ActionInput,
+    mut particle_inspector: ResMut<ParticleInspector>,
+) {
+    if action_input.just_pressed(Action::ToggleParticleInspector) {
+        particle_inspector.0 = !particle_inspector.0;
+    }
+}
+
+fn hide_particle_inspector(mut panel_query: Query<&mut Style, With<ParticleInspectorPanel>>) {
+    for mut style in &mut panel_query {
+        if style.display != Display::None {
+            style.display = Display::None;
+        }
+    }
+}
+
+fn update_particle_inspector(
+    cursor_tile_position: Res<CursorTilePosition>,
+    chunk_positions: Res<ChunkPositions>,
+    chunk_query: Query<(&Chunk, Has<ChunkActive>)>,
+    window_query: Query<&Window, With<PrimaryWindow>>,
+    mut panel_query: Query<&mut Style, With<ParticleInspectorPanel>>,
+    mut text_query: Query<&mut Text, With<ParticleInspectorText>>,
+) {
+    let mut panel_style = panel_query.single_mut();
+
+    let cursor_position = window_query
+        .get_single()
+        .ok()
+        .and_then(|window| window.cursor_position());
+    let tile_position = cursor_tile_position.0;
+    let chunk_position = tile_pos_to_chunk_pos(tile_position);
+    let chunk = chunk_positions
+        .get_at(chunk_position)
+        .and_then(|entity| chunk_query.get(*entity).ok());
+
+    let (Some(cursor_position), Some((chunk, chunk_active))) = (cursor_position, chunk) else {
+        panel_style.display = Display::None;
+        return;
+    };
+
+    let local_position = IVec2::new(
+        positive_mod(tile_position.x, CHUNK_SIZE),
+        positive_mod(tile_position.y, CHUNK_SIZE),
+    );
+    let chunk_data = chunk.read().unwrap();
+    let Some(particle) = chunk_data.get_particle(local_position) else {
+        panel_style.display = Display::None;
+        return;
+    };
+    let attributes = chunk_data.attributes();
+    let velocity = attributes.velocity.get(particle.id()).copied();
+    let momentum = attributes.momentum.get(particle.id()).copied();
+
+    let mut text = text_query.single_mut();
+    text.sections[0].value = format!(
+        "Material: {}\nId: {}\nDirty: {}\nColor seed: {}\nVelocity: {}\nMomentum: {}\nTile: {}\nChunk: {} ({})",
+        particle.material(),
+        u16::from(particle.id()),
+        particle.dirty(),
+        particle.color_seed(),
+        velocity.map_or("-".to_string(), |v| v.to_string()),
+        momentum.map_or("-".to_string(), |m| m.to_string()),
+        tile_position,
+        chunk_position,
+        if chunk_active { "active" } else { "inactive" },
+    );
+
+    panel_style.display = Display::Flex;
+    panel_style.left = Val::Px(cursor_position.x + PANEL_CURSOR_OFFSET);
+    panel_style.top = Val::Px(cursor_position.y + PANEL_CURSOR_OFFSET);
+}
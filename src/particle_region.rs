@@ -0,0 +1,145 @@
+use bevy::math::IVec2;
+use ndarray::Array2;
+
+use crate::{material::Material, particle_attributes::ParticleAttributeValues};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RegionParticle {
+    pub material: Material,
+    /// Color seed the particle is written back with, or `None` to pick a new one like a brush
+    /// does.
+    pub color_seed: Option<u8>,
+    pub attributes: ParticleAttributeValues,
+}
+
+impl Default for RegionParticle {
+    fn default() -> Self {
+        RegionParticle {
+            material: Material::Air,
+            color_seed: None,
+            attributes: ParticleAttributeValues::default(),
+        }
+    }
+}
+
+/// A rectangle of particles lifted out of the world, indexed by `(x, y)` from its lower left
+/// corner.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParticleRegion {
+    particles: Array2<RegionParticle>,
+}
+
+impl ParticleRegion {
+    pub fn new(particles: Array2<RegionParticle>) -> ParticleRegion {
+        ParticleRegion { particles }
+    }
+
+    pub fn from_fn(size: IVec2, mut f: impl FnMut(IVec2) -> RegionParticle) -> ParticleRegion {
+        ParticleRegion::new(Array2::from_shape_fn(
+            (size.x as usize, size.y as usize),
+            |(x, y)| f(IVec2::new(x as i32, y as i32)),
+        ))
+    }
+
+    pub fn size(&self) -> IVec2 {
+        IVec2::new(self.particles.dim().0 as i32, self.particles.dim().1 as i32)
+    }
+
+    pub fn get(&self, position: IVec2) -> Option<&RegionParticle> {
+        self.particles
+            .get((position.x as usize, position.y as usize))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (IVec2, &RegionParticle)> {
+        self.particles
+            .indexed_iter()
+            .map(|((x, y), particle)| (IVec2::new(x as i32, y as i32), particle))
+    }
+
+    /// Rotate the region a quarter turn clockwise, turning the particle velocities with it.
+    pub fn rotated_clockwise(&self) -> ParticleRegion {
+        let size = self.size();
+        ParticleRegion::from_fn(IVec2::new(size.y, size.x), |IVec2 { x, y }| {
+            let mut particle = self.get(IVec2::new(size.x - 1 - y, x)).unwrap().clone();
+            let velocity = particle.attributes.velocity;
+            particle.attributes.velocity = IVec2::new(velocity.y, -velocity.x);
+            particle
+        })
+    }
+
+    /// Mirror the region along the vertical axis, so left becomes right.
+    pub fn flipped_horizontally(&self) -> ParticleRegion {
+        let size = self.size();
+        ParticleRegion::from_fn(size, |IVec2 { x, y }| {
+            let mut particle = self.get(IVec2::new(size.x - 1 - x, y)).unwrap().clone();
+            particle.attributes.velocity.x = -particle.attributes.velocity.x;
+            particle
+        })
+    }
+
+    /// Mirror the region along the horizontal axis, so top becomes bottom.
+    pub fn flipped_vertically(&self) -> ParticleRegion {
+        let size = self.size();
+        ParticleRegion::from_fn(size, |IVec2 { x, y }| {
+            let mut particle = self.get(IVec2::new(x, size.y - 1 - y)).unwrap().clone();
+            particle.attributes.velocity.y = -particle.attributes.velocity.y;
+            particle
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn test_region() -> ParticleRegion {
+        // 3 wide, 2 high, with sand in the lower left corner falling down
+        ParticleRegion::from_fn(IVec2::new(3, 2), |position| {
+            if position == IVec2::ZERO {
+                RegionParticle {
+                    material: Material::Sand,
+                    color_seed: Some(7),
+                    attributes: ParticleAttributeValues {
+                        velocity: IVec2::NEG_Y,
+                        ..Default::default()
+                    },
+                }
+            } else {
+                RegionParticle::default()
+            }
+        })
+    }
+
+    #[test]
+    fn test_rotated_clockwise() {
+        let rotated = test_region().rotated_clockwise();
+        assert_eq!(rotated.size(), IVec2::new(2, 3));
+
+        // The lower left corner ends up in the upper left corner, falling to the left
+        let corner = rotated.get(IVec2::new(0, 2)).unwrap();
+        assert_eq!(corner.material, Material::Sand);
+        assert_eq!(corner.attributes.velocity, IVec2::NEG_X);
+
+        let full_turn = rotated
+            .rotated_clockwise()
+            .rotated_clockwise()
+            .rotated_clockwise();
+        assert_eq!(full_turn, test_region());
+    }
+
+    #[test]
+    fn test_flipped() {
+        let flipped = test_region().flipped_horizontally();
+        assert_eq!(
+            flipped.get(IVec2::new(2, 0)).unwrap().material,
+            Material::Sand
+        );
+        assert_eq!(flipped.flipped_horizontally(), test_region());
+
+        let flipped = test_region().flipped_vertically();
+        let corner = flipped.get(IVec2::new(0, 1)).unwrap();
+        assert_eq!(corner.material, Material::Sand);
+        assert_eq!(corner.attributes.velocity, IVec2::Y);
+        assert_eq!(flipped.flipped_vertically(), test_region());
+    }
+}
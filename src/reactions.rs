@@ -1,9 +1,15 @@
-use bevy::{ecs::system::Res, log::info_span, math::IVec2};
+use bevy::{
+    ecs::system::{Res, ResMut},
+    log::info_span,
+    math::IVec2,
+    utils::Instant,
+};
 use rand::seq::SliceRandom;
 use smallvec::SmallVec;
 
 use crate::{
     chunk_neighborhood_view::ChunkNeighborhoodView,
+    falling_sand::{SimulationPass, SimulationPassTimings},
     material::{Material, MaterialReactions},
     process_chunks::{process_chunks_neighborhood, ChunksParam},
     util::random_dir_range,
@@ -11,10 +17,16 @@ use crate::{
 
 type ReactionChoices = SmallVec<[(Material, u32); 8]>;
 
-pub fn react(grid: ChunksParam, material_reactions: Res<MaterialReactions>) {
+pub fn react(
+    grid: ChunksParam,
+    material_reactions: Res<MaterialReactions>,
+    mut timings: ResMut<SimulationPassTimings>,
+) {
+    let start = Instant::now();
     process_chunks_neighborhood(&grid, |_chunk_pos, grid| {
         react_chunk(grid, &material_reactions)
     });
+    timings[SimulationPass::React] = start.elapsed();
 }
 
 pub fn react_chunk(grid: &mut ChunkNeighborhoodView, material_reactions: &MaterialReactions) {
@@ -0,0 +1,486 @@
+use std::{
+    borrow::Cow,
+    error::Error,
+    fs::{self, File},
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Receiver, SyncSender, TrySendError},
+    thread,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use bevy::{
+    app::{App, FixedUpdate, Plugin, Update},
+    core_pipeline::core_2d::Camera2d,
+    ecs::{
+        query::With,
+        schedule::IntoSystemConfigs,
+        system::{Query, Res, ResMut, Resource},
+    },
+    gizmos::gizmos::Gizmos,
+    log::{info, warn},
+    math::{IVec2, UVec2},
+    render::{camera::OrthographicProjection, color::Color},
+    time::{Fixed, Time},
+    transform::components::Transform,
+    utils::default,
+};
+use bytemuck::cast_slice;
+use enum_map::Enum;
+
+use crate::{
+    draw_tool::{get_tile_at_world_position, tile_rect_world_bounds},
+    export::{rasterize_tiles, save_png_pixels, scale_pixels},
+    falling_sand::{FallingSandSet, FallingSandSettings},
+    falling_sand_grid::FallingSandGridQuery,
+    input_map::{Action, ActionInput},
+    material::Material,
+    particle_grid::Particle,
+    render::cpu::ParticlePalette,
+    selection::Selection,
+};
+
+pub struct RecordingPlugin {
+    pub settings: RecordingSettings,
+}
+
+impl Plugin for RecordingPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(self.settings.clone())
+            .init_resource::<Recording>()
+            .add_systems(Update, (toggle_recording, draw_recording_gizmos).chain())
+            .add_systems(FixedUpdate, record_frame.after(FallingSandSet));
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RecordingFormat {
+    /// A single looping animated GIF.
+    Gif,
+    /// A directory of numbered PNG frames.
+    Png,
+}
+
+#[derive(Resource, Clone)]
+pub struct RecordingSettings {
+    /// Number of simulation ticks between recorded frames.
+    pub interval: u32,
+    /// Width and height in pixels of a tile in the recorded frames.
+    pub scale: u32,
+    pub format: RecordingFormat,
+    pub directory: PathBuf,
+    /// Recordings stop by themselves after this many frames.
+    pub max_frames: u32,
+    /// Largest width or height in pixels of the recorded frames. Larger areas are recorded at a
+    /// lower resolution.
+    pub max_size: u32,
+}
+
+impl Default for RecordingSettings {
+    fn default() -> Self {
+        Self {
+            interval: 2,
+            scale: 1,
+            format: RecordingFormat::Gif,
+            directory: PathBuf::from("recordings"),
+            max_frames: 3000,
+            max_size: 1024,
+        }
+    }
+}
+
+/// Memory the captured frames waiting for the writer thread can take up. When it falls further
+/// behind, frames are dropped rather than piling up.
+const FRAME_QUEUE_BYTES: usize = 64 << 20;
+
+/// How the tiles of a recorded area map to the pixels of its frames.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct FrameLayout {
+    /// Distance between the recorded tiles, every `step`th tile is recorded.
+    step: u32,
+    /// Number of recorded tiles along each axis.
+    tiles: UVec2,
+    /// Width and height in pixels of a recorded tile.
+    scale: u32,
+}
+
+impl FrameLayout {
+    /// Layout of frames of an `area` tiles large, drawn `scale` pixels per tile but no more than
+    /// `max_size` pixels wide or high.
+    fn new(area: UVec2, scale: u32, max_size: u32) -> Self {
+        let max_size = max_size.max(1);
+        let largest = area.max_element().max(1);
+        let step = largest.div_ceil(max_size);
+        FrameLayout {
+            step,
+            tiles: (area + step - 1) / step,
+            scale: scale.min(max_size / largest).max(1),
+        }
+    }
+
+    fn size(&self) -> UVec2 {
+        self.tiles * self.scale
+    }
+}
+
+/// Recording in progress of the tile rectangle from `min` to `max`. Frames are captured in
+/// `FixedUpdate`, so they follow the simulation and not the display frame rate, with a single
+/// lookup per recorded tile. They are scaled, encoded and written by a writer thread. The
+/// recording is finished when this is dropped.
+struct ActiveRecording {
+    min: IVec2,
+    max: IVec2,
+    layout: FrameLayout,
+    ticks: u32,
+    frame_count: u32,
+    dropped_frames: u32,
+    /// Unscaled frames as `gif_palette_index` values for GIF recordings, RGBA pixels for PNG
+    /// recordings.
+    frames: SyncSender<Vec<u8>>,
+}
+
+#[derive(Resource, Default)]
+struct Recording(Option<ActiveRecording>);
+
+/// Shades of every material in the palette of recorded GIFs, out of the 256 color seeds.
+const GIF_SHADES: usize = 16;
+const _: () = assert!(Material::LENGTH * GIF_SHADES <= 256);
+
+fn gif_palette_index(particle: Particle) -> u8 {
+    let material = u16::from(particle.material()) as usize;
+    (material * GIF_SHADES + particle.color_seed() as usize / (256 / GIF_SHADES)) as u8
+}
+
+/// Colors of all `gif_palette_index` values, using the color seed in the middle of every shade.
+fn gif_palette(palette: &ParticlePalette) -> Vec<[u8; 3]> {
+    (0..Material::LENGTH * GIF_SHADES)
+        .map(|index| {
+            let mut particle = Particle::new(Material::from_usize(index / GIF_SHADES), 0);
+            let shade_seeds = 256 / GIF_SHADES;
+            particle.set_color_seed(((index % GIF_SHADES) * shade_seeds + shade_seeds / 2) as u8);
+            let [r, g, b, _] = palette.particle_color(particle);
+            [r, g, b]
+        })
+        .collect()
+}
+
+/// Width and height of a GIF frame of `size` pixels, which can't be larger than `u16::MAX`.
+fn gif_size(size: UVec2) -> Option<(u16, u16)> {
+    Some((u16::try_from(size.x).ok()?, u16::try_from(size.y).ok()?))
+}
+
+/// Tile rectangle the main camera shows.
+fn visible_tiles(
+    transform: &Transform,
+    projection: &OrthographicProjection,
+    falling_sand_settings: &FallingSandSettings,
+) -> (IVec2, IVec2) {
+    let grid_size = IVec2::new(
+        falling_sand_settings.size.0 as i32,
+        falling_sand_settings.size.1 as i32,
+    );
+    let center = transform.translation.truncate();
+    let [min, max] = [projection.area.min, projection.area.max].map(|corner| {
+        get_tile_at_world_position(center + corner, grid_size, falling_sand_settings.tile_size)
+    });
+    (min, max)
+}
+
+/// Where the writer thread of a recording writes its frames to.
+enum RecordingOutput {
+    Gif {
+        path: PathBuf,
+        size: (u16, u16),
+        palette: Vec<u8>,
+        /// Time between frames in centiseconds.
+        delay: u16,
+    },
+    Png {
+        directory: PathBuf,
+        size: UVec2,
+    },
+}
+
+impl RecordingOutput {
+    fn path(&self) -> &Path {
+        match self {
+            RecordingOutput::Gif { path, .. } => path,
+            RecordingOutput::Png { directory, .. } => directory,
+        }
+    }
+
+    /// Scale and write the frames received from `frames` until the recording is dropped,
+    /// returning the number of frames written.
+    fn write(self, layout: FrameLayout, frames: Receiver<Vec<u8>>) -> Result<u32, Box<dyn Error>> {
+        let tile_width = layout.tiles.x as usize;
+        let mut frame_count = 0;
+        match self {
+            RecordingOutput::Gif {
+                path,
+                size: (width, height),
+                palette,
+                delay,
+            } => {
+                if let Some(directory) = path.parent() {
+                    fs::create_dir_all(directory)?;
+                }
+                let file = BufWriter::new(File::create(&path)?);
+                let mut encoder = gif::Encoder::new(file, width, height, &palette)?;
+                encoder.set_repeat(gif::Repeat::Infinite)?;
+                for indices in frames {
+                    encoder.write_frame(&gif::Frame {
+                        width,
+                        height,
+                        delay,
+                        buffer: Cow::Owned(scale_pixels(&indices, tile_width, layout.scale)),
+                        ..default()
+                    })?;
+                    frame_count += 1;
+                }
+                encoder.into_inner()?.flush()?;
+            }
+            RecordingOutput::Png { directory, size } => {
+                for pixels in frames {
+                    frame_count += 1;
+                    let path = directory.join(format!("frame-{:05}.png", frame_count));
+                    let colors = cast_slice::<u8, [u8; 4]>(&pixels);
+                    let pixels = scale_pixels(colors, tile_width, layout.scale).concat();
+                    save_png_pixels(&path, size, pixels)?;
+                }
+            }
+        }
+        Ok(frame_count)
+    }
+}
+
+fn start_recording(
+    (min, max): (IVec2, IVec2),
+    recording_settings: &RecordingSettings,
+    fixed_time: &Time<Fixed>,
+    palette: &ParticlePalette,
+) -> Result<ActiveRecording, String> {
+    let layout = FrameLayout::new(
+        (max - min + IVec2::ONE).as_uvec2(),
+        recording_settings.scale,
+        recording_settings.max_size,
+    );
+    let size = layout.size();
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let name = format!("recording-{}", timestamp);
+
+    let output = match recording_settings.format {
+        RecordingFormat::Gif => RecordingOutput::Gif {
+            path: recording_settings.directory.join(format!("{}.gif", name)),
+            size: gif_size(size).ok_or_else(|| {
+                format!(
+                    "{}x{} pixels is too large for a GIF, record PNG frames or a smaller area",
+                    size.x, size.y
+                )
+            })?,
+            palette: gif_palette(palette).concat(),
+            // Play back at the speed of the simulation. Most viewers don't go below 2
+            // centiseconds.
+            delay: (fixed_time.timestep().as_secs_f64() * recording_settings.interval as f64 * 100.)
+                .round()
+                .max(2.) as u16,
+        },
+        RecordingFormat::Png => RecordingOutput::Png {
+            directory: recording_settings.directory.join(&name),
+            size,
+        },
+    };
+
+    let frame_bytes = match recording_settings.format {
+        RecordingFormat::Gif => 1,
+        RecordingFormat::Png => 4,
+    } * (layout.tiles.x * layout.tiles.y) as usize;
+    let (sender, receiver) = mpsc::sync_channel((FRAME_QUEUE_BYTES / frame_bytes).max(1));
+    thread::Builder::new()
+        .name("recording writer".into())
+        .spawn(move || {
+            let path = output.path().to_owned();
+            match output.write(layout, receiver) {
+                Ok(frame_count) => info!("Recorded {} frames to {}", frame_count, path.display()),
+                Err(error) => warn!("Failed to save recording {}: {}", path.display(), error),
+            }
+        })
+        .map_err(|error| error.to_string())?;
+
+    if layout.step > 1 {
+        info!(
+            "Recording every {} tiles to stay within {} pixels",
+            layout.step, recording_settings.max_size
+        );
+    }
+    info!("Recording {}x{} pixels", size.x, size.y);
+    Ok(ActiveRecording {
+        min,
+        max,
+        layout,
+        ticks: 0,
+        frame_count: 0,
+        dropped_frames: 0,
+        frames: sender,
+    })
+}
+
+fn finish_recording(active_recording: ActiveRecording) {
+    if active_recording.dropped_frames > 0 {
+        warn!(
+            "Dropped {} of {} frames, the recording couldn't be written fast enough",
+            active_recording.dropped_frames, active_recording.frame_count
+        );
+    }
+    // Dropping the sender lets the writer thread finish the file
+}
+
+#[allow(clippy::too_many_arguments)]
+fn toggle_recording(
+    action_input: ActionInput,
+    mut recording: ResMut<Recording>,
+    recording_settings: Res<RecordingSettings>,
+    selection: Res<Selection>,
+    camera_query: Query<(&Transform, &OrthographicProjection), With<Camera2d>>,
+    falling_sand_settings: Res<FallingSandSettings>,
+    fixed_time: Res<Time<Fixed>>,
+    palette: Res<ParticlePalette>,
+) {
+    if !action_input.just_pressed(Action::ToggleRecording) {
+        return;
+    }
+
+    if let Some(active_recording) = recording.0.take() {
+        finish_recording(active_recording);
+        return;
+    }
+
+    // Record the selection if there is one, otherwise what's on screen
+    let Some(bounds) = selection.0.or_else(|| {
+        let (transform, projection) = camera_query.get_single().ok()?;
+        Some(visible_tiles(transform, projection, &falling_sand_settings))
+    }) else {
+        return;
+    };
+
+    match start_recording(bounds, &recording_settings, &fixed_time, &palette) {
+        Ok(active_recording) => recording.0 = Some(active_recording),
+        Err(error) => warn!("Failed to start recording: {}", error),
+    }
+}
+
+fn record_frame(
+    mut recording: ResMut<Recording>,
+    recording_settings: Res<RecordingSettings>,
+    grid: FallingSandGridQuery,
+    palette: Res<ParticlePalette>,
+) {
+    let Some(active_recording) = recording.0.as_mut() else {
+        return;
+    };
+
+    active_recording.ticks += 1;
+    if active_recording.ticks < recording_settings.interval.max(1) {
+        return;
+    }
+    active_recording.ticks = 0;
+    active_recording.frame_count += 1;
+
+    // Tiles without a chunk are recorded as air
+    let particle_at = |position| {
+        grid.get_particle(position)
+            .unwrap_or(Particle::new(Material::Air, 0))
+    };
+    let (min, layout) = (active_recording.min, active_recording.layout);
+    let recorded_tile = |tile: IVec2| particle_at(min + tile * layout.step as i32);
+    let last_tile = layout.tiles.as_ivec2() - IVec2::ONE;
+    let frame = match recording_settings.format {
+        RecordingFormat::Gif => rasterize_tiles(IVec2::ZERO, last_tile, |tile| {
+            gif_palette_index(recorded_tile(tile))
+        }),
+        RecordingFormat::Png => rasterize_tiles(IVec2::ZERO, last_tile, |tile| {
+            palette.particle_color(recorded_tile(tile))
+        })
+        .concat(),
+    };
+
+    let writer_stopped = match active_recording.frames.try_send(frame) {
+        Ok(()) => false,
+        Err(TrySendError::Full(_)) => {
+            active_recording.dropped_frames += 1;
+            false
+        }
+        // The writer thread already logged why it failed
+        Err(TrySendError::Disconnected(_)) => true,
+    };
+    if writer_stopped || active_recording.frame_count >= recording_settings.max_frames {
+        finish_recording(recording.0.take().unwrap());
+    }
+}
+
+fn draw_recording_gizmos(
+    mut gizmos: Gizmos,
+    recording: Res<Recording>,
+    falling_sand_settings: Res<FallingSandSettings>,
+) {
+    let Some(active_recording) = &recording.0 else {
+        return;
+    };
+
+    let (center, size) = tile_rect_world_bounds(
+        &falling_sand_settings,
+        active_recording.min,
+        active_recording.max,
+    );
+    gizmos.rect_2d(center, 0., size, Color::RED);
+}
+
+#[cfg(test)]
+mod test {
+    use crate::material::{MaterialColor, MaterialColorVariation};
+
+    use super::*;
+
+    #[test]
+    fn test_frame_layout_stays_within_max_size() {
+        let layout = FrameLayout::new(UVec2::new(100, 50), 4, 1024);
+        assert_eq!(layout.step, 1);
+        assert_eq!(layout.size(), UVec2::new(400, 200));
+
+        // Tiles are drawn smaller before any are skipped
+        let layout = FrameLayout::new(UVec2::new(400, 50), 4, 1024);
+        assert_eq!((layout.step, layout.scale), (1, 2));
+
+        let layout = FrameLayout::new(UVec2::new(3000, 100), 2, 1024);
+        assert_eq!((layout.step, layout.scale), (3, 1));
+        assert_eq!(layout.tiles, UVec2::new(1000, 34));
+    }
+
+    #[test]
+    fn test_gif_size() {
+        assert_eq!(gif_size(UVec2::new(640, 480)), Some((640, 480)));
+        assert_eq!(gif_size(UVec2::new(65535, 1)), Some((65535, 1)));
+        assert_eq!(gif_size(UVec2::new(1, 65536)), None);
+    }
+
+    #[test]
+    fn test_gif_palette_index_matches_palette() {
+        let palette = ParticlePalette::new(
+            &MaterialColor::default(),
+            &MaterialColorVariation::default(),
+        );
+        let gif_palette = gif_palette(&palette);
+
+        let mut particle = Particle::new(Material::Water, 0);
+        particle.set_color_seed(200);
+        let index = gif_palette_index(particle) as usize;
+        assert_eq!(index / GIF_SHADES, u16::from(Material::Water) as usize);
+        let [r, g, b, _] = palette.particle_color(particle);
+        let color_distance = |a: u8, b: u8| a.abs_diff(b);
+        assert!(color_distance(gif_palette[index][0], r) <= 16);
+        assert!(color_distance(gif_palette[index][1], g) <= 16);
+        assert!(color_distance(gif_palette[index][2], b) <= 16);
+    }
+}
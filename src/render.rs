@@ -14,9 +14,10 @@ use bevy::{
         render_graph::{self, RenderLabel},
         render_resource::{
             BindGroup, BindGroupEntries, BindGroupLayout, BindGroupLayoutEntry, BindingType,
-            CachedComputePipelineId, CachedPipelineState, ComputePassDescriptor,
-            ComputePipelineDescriptor, PipelineCache, ShaderStages, StorageTextureAccess,
-            TextureFormat, TextureSampleType, TextureViewDimension,
+            BufferBindingType, BufferInitDescriptor, BufferUsages, CachedComputePipelineId,
+            CachedPipelineState, ComputePassDescriptor, ComputePipelineDescriptor, PipelineCache,
+            ShaderDefVal, ShaderStages, StorageTextureAccess, TextureFormat, TextureSampleType,
+            TextureViewDimension,
         },
         renderer::RenderDevice,
         settings::WgpuFeatures,
@@ -24,13 +25,19 @@ use bevy::{
         ExtractSchedule, Render, RenderApp, RenderSet,
     },
 };
+use bytemuck::cast_slice;
 use itertools::Itertools;
 use tracing::{info, info_span};
 
-use crate::falling_sand::FallingSandSettings;
+use crate::{consts::CHUNK_SIZE, falling_sand::FallingSandSettings};
 
-use self::extract::ExtractedChunkUpdate;
+use self::{
+    atlas::{ChunkAtlas, ATLAS_SLOTS_PER_ROW},
+    extract::ExtractedChunkUpdate,
+};
 
+pub mod atlas;
+pub mod cpu;
 pub mod extract;
 
 pub struct FallingSandRenderPlugin;
@@ -75,6 +82,15 @@ which is required for texture binding arrays"
     }
 }
 
+/// Whether the render device can run the compute pipeline, which WebGL2 and other downlevel
+/// devices can't.
+pub fn compute_renderer_supported(render_device: &RenderDevice) -> bool {
+    let limits = render_device.limits();
+    limits.max_compute_workgroups_per_dimension > 0
+        && limits.max_storage_textures_per_shader_stage > 0
+        && limits.max_storage_buffers_per_shader_stage > 0
+}
+
 fn bindless_supported(render_device: &RenderDevice) -> bool {
     render_device
         .features()
@@ -100,6 +116,7 @@ fn prepare_bind_group(
     pipeline: Res<FallingSandPipeline>,
     image_assets: Res<RenderAssets<Image>>,
     falling_sand_images: Res<FallingSandImages>,
+    atlas: Res<ChunkAtlas>,
     extracted_chunks: Query<&ExtractedChunkUpdate>,
     mut falling_sand_images_bind_groups: ResMut<FallingSandImagesBindGroups>,
     render_device: Res<RenderDevice>,
@@ -108,24 +125,28 @@ fn prepare_bind_group(
         .get(falling_sand_images.color_map.clone())
         .unwrap()
         .texture_view;
-
     falling_sand_images_bind_groups.0.clear();
+    // `extract` only queues chunks once the atlas is prepared
+    let Some(atlas_image) = image_assets.get(&atlas.image) else {
+        return;
+    };
+    let atlas_texture = &atlas_image.texture_view;
+    let create_slots_buffer = |slots: &[u32]| {
+        render_device.create_buffer_with_data(&BufferInitDescriptor {
+            label: Some("chunk_slots_buffer"),
+            contents: cast_slice(slots),
+            usage: BufferUsages::STORAGE,
+        })
+    };
 
     if bindless_supported(&render_device) {
         let extracted_chunks: Vec<_> = extracted_chunks.iter().collect();
 
         for chunks in &extracted_chunks.iter().chunks(MAX_TEXTURE_COUNT) {
-            let (grid_textures, color_textures): (Vec<_>, Vec<_>) = chunks.fold(
-                (
-                    Vec::with_capacity(MAX_TEXTURE_COUNT),
-                    Vec::with_capacity(MAX_TEXTURE_COUNT),
-                ),
-                |(mut grid_textures, mut color_textures), images| {
-                    grid_textures.push(&*images.materials_texture.default_view);
-                    color_textures.push(&*images.color_texture);
-                    (grid_textures, color_textures)
-                },
-            );
+            let (grid_textures, slots): (Vec<_>, Vec<_>) = chunks
+                .map(|chunk_update| (&*chunk_update.materials_texture, chunk_update.slot.0))
+                .unzip();
+            let slots_buffer = create_slots_buffer(&slots);
 
             let bind_group = render_device.create_bind_group(
                 "bindless_grid_material_bind_group",
@@ -133,7 +154,8 @@ fn prepare_bind_group(
                 &BindGroupEntries::sequential((
                     &grid_textures[..],
                     color_map_texture,
-                    &color_textures[..],
+                    atlas_texture,
+                    slots_buffer.as_entire_binding(),
                 )),
             );
 
@@ -143,13 +165,18 @@ fn prepare_bind_group(
         }
     } else {
         for chunk_update in extracted_chunks.iter() {
-            let grid_texture = &chunk_update.materials_texture.default_view;
-            let color_texture = &chunk_update.color_texture;
+            let grid_texture = &chunk_update.materials_texture;
+            let slots_buffer = create_slots_buffer(&[chunk_update.slot.0]);
 
             let bind_group = render_device.create_bind_group(
                 "grid_material_bind_group",
                 &pipeline.texture_bind_group_layout,
-                &BindGroupEntries::sequential((grid_texture, color_map_texture, color_texture)),
+                &BindGroupEntries::sequential((
+                    grid_texture,
+                    color_map_texture,
+                    atlas_texture,
+                    slots_buffer.as_entire_binding(),
+                )),
             );
 
             falling_sand_images_bind_groups.0.push((1, bind_group));
@@ -183,7 +210,7 @@ impl FromWorld for FallingSandPipeline {
                     visibility: ShaderStages::COMPUTE,
                     ty: BindingType::Texture {
                         sample_type: TextureSampleType::Float { filterable: false },
-                        view_dimension: TextureViewDimension::D1,
+                        view_dimension: TextureViewDimension::D2,
                         multisampled: false,
                     },
                     count: None,
@@ -193,14 +220,20 @@ impl FromWorld for FallingSandPipeline {
                     visibility: ShaderStages::COMPUTE,
                     ty: BindingType::StorageTexture {
                         access: StorageTextureAccess::WriteOnly,
-                        format: TextureFormat::Rgba32Float,
+                        format: TextureFormat::Rgba16Float,
                         view_dimension: TextureViewDimension::D2,
                     },
-                    count: if bindless_supported {
-                        NonZeroU32::new(MAX_TEXTURE_COUNT as u32)
-                    } else {
-                        None
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
                     },
+                    count: None,
                 },
             ],
         );
@@ -210,11 +243,13 @@ impl FromWorld for FallingSandPipeline {
             .load("shaders/grid_to_texture.wgsl");
 
         let pipeline_cache = world.resource_mut::<PipelineCache>();
-        let shader_defs = if bindless_supported {
-            vec!["BINDLESS".into()]
-        } else {
-            vec![]
-        };
+        let mut shader_defs = vec![
+            ShaderDefVal::UInt("ATLAS_SLOTS_PER_ROW".into(), ATLAS_SLOTS_PER_ROW),
+            ShaderDefVal::Int("CHUNK_SIZE".into(), CHUNK_SIZE),
+        ];
+        if bindless_supported {
+            shader_defs.push("BINDLESS".into());
+        }
         let render_grid_pipeline =
             pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
                 label: Some("render_chunk_pipeline".into()),
@@ -0,0 +1,374 @@
+use bevy::{
+    asset::{Assets, Handle},
+    ecs::{
+        change_detection::DetectChanges,
+        component::Component,
+        entity::Entity,
+        query::With,
+        system::{Commands, Local, Query, Res, ResMut, Resource},
+        world::{FromWorld, World},
+    },
+    log::warn,
+    math::{IVec2, Vec2, Vec2Swizzles},
+    render::{
+        color::Color,
+        extract_resource::ExtractResource,
+        mesh::{Indices, Mesh, PrimitiveTopology},
+        render_asset::RenderAssetUsages,
+        render_resource::{Extent3d, TextureDimension, TextureFormat, TextureUsages},
+        renderer::RenderDevice,
+        texture::Image,
+        view::NoFrustumCulling,
+    },
+    sprite::{ColorMaterial, MaterialMesh2dBundle, Mesh2dHandle},
+    utils::{default, HashSet},
+};
+use bytemuck::cast_slice;
+use half::f16;
+
+use crate::{
+    chunk::Chunk,
+    consts::CHUNK_SIZE,
+    falling_sand::{ChunkPosition, ChunkRenderer, FallingSandSettings},
+    material::{Material, MaterialColor},
+    visible_chunks::VisibleChunks,
+};
+
+/// Number of chunk slots in a row of the atlas.
+pub const ATLAS_SLOTS_PER_ROW: u32 = 32;
+/// Rows of the atlas before it first has to grow, enough for the chunks on screen at startup.
+const INITIAL_ATLAS_ROWS: u32 = 16;
+/// Texture size limit when there is no render device to ask, wgpu's default
+/// `max_texture_dimension_2d`.
+const DEFAULT_MAX_TEXTURE_SIZE: u32 = 8192;
+/// Chunks this far off screen keep their slot, so panning back and forth doesn't redraw them.
+const SLOT_RELEASE_DISTANCE: i32 = 1;
+
+/// Slot of a chunk's sprite in the `ChunkAtlas`. Only chunks on screen or close to it have one.
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AtlasSlot(pub u32);
+
+impl AtlasSlot {
+    /// Texel of the slot's top left corner. Like the particle grid textures, a slot has a row
+    /// per x coordinate of the chunk.
+    pub fn origin(&self) -> IVec2 {
+        IVec2::new(
+            (self.0 % ATLAS_SLOTS_PER_ROW) as i32,
+            (self.0 / ATLAS_SLOTS_PER_ROW) as i32,
+        ) * CHUNK_SIZE
+    }
+}
+
+/// Texture holding the colored particles of the chunks on screen, so they are drawn with a single
+/// mesh.
+#[derive(Resource, Clone, ExtractResource)]
+pub struct ChunkAtlas {
+    pub image: Handle<Image>,
+    pub rows: u32,
+    /// Rows the atlas can grow to before it's larger than the render device supports.
+    max_rows: u32,
+    renderer: ChunkRenderer,
+    fill_color: Color,
+}
+
+impl FromWorld for ChunkAtlas {
+    fn from_world(world: &mut World) -> Self {
+        let renderer = *world.resource::<ChunkRenderer>();
+        let fill_color = world.resource::<MaterialColor>()[Material::Air];
+        let max_texture_size = world
+            .get_resource::<RenderDevice>()
+            .map_or(DEFAULT_MAX_TEXTURE_SIZE, |render_device| {
+                render_device.limits().max_texture_dimension_2d
+            });
+        let max_rows = (max_texture_size / CHUNK_SIZE as u32).max(1);
+        let rows = INITIAL_ATLAS_ROWS.min(max_rows);
+        let image = create_atlas_image(rows, renderer, fill_color);
+        ChunkAtlas {
+            image: world.resource_mut::<Assets<Image>>().add(image),
+            rows,
+            max_rows,
+            renderer,
+            fill_color,
+        }
+    }
+}
+
+impl ChunkAtlas {
+    pub fn size(&self) -> IVec2 {
+        IVec2::new(ATLAS_SLOTS_PER_ROW as i32, self.rows as i32) * CHUNK_SIZE
+    }
+
+    pub fn capacity(&self) -> u32 {
+        self.rows * ATLAS_SLOTS_PER_ROW
+    }
+
+    /// Grow the atlas so it holds at least `slots` slots, as far as the render device allows.
+    /// Returns whether it grew, in which case it's empty and every chunk has to be drawn again.
+    fn grow_to(&mut self, slots: u32, images: &mut Assets<Image>) -> bool {
+        let rows = atlas_rows_for(slots, self.rows, self.max_rows);
+        if rows == self.rows {
+            return false;
+        }
+        self.rows = rows;
+        self.image = images.add(create_atlas_image(
+            self.rows,
+            self.renderer,
+            self.fill_color,
+        ));
+        true
+    }
+}
+
+/// Rows of an atlas with `rows` rows after doubling them until there are `slots` slots, but no
+/// more than `max_rows`.
+fn atlas_rows_for(slots: u32, rows: u32, max_rows: u32) -> u32 {
+    let mut rows = rows;
+    while rows * ATLAS_SLOTS_PER_ROW < slots && rows < max_rows {
+        rows = (rows * 2).min(max_rows);
+    }
+    rows
+}
+
+/// Give the chunks that came on screen a slot in the atlas, reusing the slots of chunks that went
+/// far enough off screen or were despawned.
+pub fn assign_atlas_slots(
+    mut commands: Commands,
+    mut warned_full: Local<bool>,
+    mut atlas: ResMut<ChunkAtlas>,
+    mut images: ResMut<Assets<Image>>,
+    visible_chunks: Res<VisibleChunks>,
+    chunk_query: Query<(Entity, &Chunk, &ChunkPosition, Option<&AtlasSlot>)>,
+) {
+    let mut used_slots = HashSet::new();
+    let mut unassigned_chunks = Vec::new();
+    for (entity, chunk, position, slot) in chunk_query.iter() {
+        match slot {
+            Some(_) if visible_chunks.distance(position.0) > SLOT_RELEASE_DISTANCE => {
+                commands.entity(entity).remove::<AtlasSlot>();
+            }
+            Some(slot) => {
+                used_slots.insert(slot.0);
+            }
+            None if visible_chunks.contains(position.0) => {
+                unassigned_chunks.push((entity, chunk));
+            }
+            None => {}
+        }
+    }
+    if unassigned_chunks.is_empty() {
+        return;
+    }
+
+    let slots_needed = (used_slots.len() + unassigned_chunks.len()) as u32;
+    if slots_needed > atlas.capacity() && atlas.grow_to(slots_needed, &mut images) {
+        // The new atlas is empty, so the chunks keeping their slot have to be drawn again
+        for (_, chunk, _, slot) in chunk_query.iter() {
+            if slot.is_some_and(|slot| used_slots.contains(&slot.0)) {
+                chunk.write().unwrap().set_texture_dirty();
+            }
+        }
+    }
+
+    let mut free_slots = (0..atlas.capacity()).filter(|slot| !used_slots.contains(slot));
+    for (entity, chunk) in unassigned_chunks {
+        let Some(slot) = free_slots.next() else {
+            if !*warned_full {
+                warn!("Chunk atlas is full, some chunks on screen aren't drawn");
+                *warned_full = true;
+            }
+            return;
+        };
+        // The slot still holds whatever chunk had it before
+        chunk.write().unwrap().set_texture_dirty();
+        commands.entity(entity).insert(AtlasSlot(slot));
+    }
+}
+
+fn create_atlas_image(rows: u32, renderer: ChunkRenderer, fill_color: Color) -> Image {
+    let size = Extent3d {
+        width: ATLAS_SLOTS_PER_ROW * CHUNK_SIZE as u32,
+        height: rows * CHUNK_SIZE as u32,
+        depth_or_array_layers: 1,
+    };
+    let mut atlas_image = match renderer {
+        // Written by the compute pipeline
+        ChunkRenderer::Gpu => {
+            let mut atlas_image = Image::new_fill(
+                size,
+                TextureDimension::D2,
+                cast_slice(&fill_color.as_linear_rgba_f32().map(f16::from_f32)),
+                TextureFormat::Rgba16Float,
+                RenderAssetUsages::RENDER_WORLD,
+            );
+            atlas_image.texture_descriptor.usage =
+                TextureUsages::STORAGE_BINDING | TextureUsages::TEXTURE_BINDING;
+            atlas_image
+        }
+        // Written by `extract_chunks_on_cpu`
+        ChunkRenderer::Cpu => {
+            let mut atlas_image = Image::new_fill(
+                size,
+                TextureDimension::D2,
+                &fill_color.as_rgba_u8(),
+                TextureFormat::Rgba8UnormSrgb,
+                RenderAssetUsages::RENDER_WORLD,
+            );
+            atlas_image.texture_descriptor.usage =
+                TextureUsages::COPY_DST | TextureUsages::TEXTURE_BINDING;
+            atlas_image
+        }
+    };
+    atlas_image.texture_descriptor.label = Some("chunk_atlas_texture");
+    atlas_image
+}
+
+/// Mesh with a quad for every chunk that is on screen, textured with its atlas slot.
+#[derive(Component)]
+pub struct ChunkAtlasMesh;
+
+pub fn spawn_chunk_atlas_mesh(
+    mut commands: Commands,
+    atlas: Res<ChunkAtlas>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    commands.spawn((
+        ChunkAtlasMesh,
+        MaterialMesh2dBundle::<ColorMaterial> {
+            material: materials.add(ColorMaterial::from(atlas.image.clone())),
+            ..default()
+        },
+        // The bounds of the mesh change whenever it's rebuilt
+        NoFrustumCulling,
+    ));
+}
+
+/// Range of chunk positions overlapping the world space rectangle from `min` to `max`.
+pub fn chunks_overlapping(min: Vec2, max: Vec2, chunk_world_size: f32) -> (IVec2, IVec2) {
+    let chunk_at = |position: Vec2| (position / chunk_world_size + 0.5).floor().as_ivec2();
+    (chunk_at(min), chunk_at(max))
+}
+
+/// Vertex positions and atlas UVs of a chunk's quad.
+fn chunk_quad(
+    position: IVec2,
+    slot: AtlasSlot,
+    chunk_world_size: f32,
+    atlas_size: IVec2,
+) -> [([f32; 3], [f32; 2]); 4] {
+    let center = position.as_vec2() * chunk_world_size;
+    let origin = slot.origin().as_vec2();
+    // Atlas rows follow the x axis, and columns the y axis
+    [(0, 0), (1, 0), (1, 1), (0, 1)].map(|(x, y)| {
+        let corner = IVec2::new(x, y);
+        let world = center + (corner.as_vec2() - 0.5) * chunk_world_size;
+        let texel = origin + (corner * CHUNK_SIZE).yx().as_vec2();
+        (
+            [world.x, world.y, 0.],
+            (texel / atlas_size.as_vec2()).to_array(),
+        )
+    })
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn update_chunk_atlas_mesh(
+    mut previous_chunks: Local<Vec<(IVec2, AtlasSlot)>>,
+    atlas: Res<ChunkAtlas>,
+    falling_sand_settings: Res<FallingSandSettings>,
+    visible_chunks: Res<VisibleChunks>,
+    chunk_query: Query<(&ChunkPosition, &AtlasSlot)>,
+    mut mesh_query: Query<(&mut Mesh2dHandle, &Handle<ColorMaterial>), With<ChunkAtlasMesh>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    let Ok((mut mesh_handle, material_handle)) = mesh_query.get_single_mut() else {
+        return;
+    };
+
+    let chunk_world_size = (CHUNK_SIZE * falling_sand_settings.tile_size as i32) as f32;
+    let mut chunks = chunk_query
+        .iter()
+        .filter(|(position, _)| visible_chunks.contains(position.0))
+        .map(|(position, slot)| (position.0, *slot))
+        .collect::<Vec<_>>();
+    chunks.sort_by_key(|(_, slot)| slot.0);
+
+    if atlas.is_changed() {
+        if let Some(material) = materials.get_mut(material_handle) {
+            material.texture = Some(atlas.image.clone());
+        }
+    } else if chunks == *previous_chunks {
+        return;
+    }
+
+    let quads = chunks
+        .iter()
+        .map(|(position, slot)| chunk_quad(*position, *slot, chunk_world_size, atlas.size()))
+        .collect::<Vec<_>>();
+    let indices = (0..quads.len() as u32)
+        .flat_map(|quad| [0, 1, 2, 0, 2, 3].map(|vertex| quad * 4 + vertex))
+        .collect::<Vec<_>>();
+    let vertices = quads.into_iter().flatten();
+    let (positions, uvs): (Vec<_>, Vec<_>) = vertices.unzip();
+
+    let mut mesh = Mesh::new(
+        PrimitiveTopology::TriangleList,
+        RenderAssetUsages::RENDER_WORLD,
+    );
+    mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
+    mesh.insert_indices(Indices::U32(indices));
+    *mesh_handle = meshes.add(mesh).into();
+    *previous_chunks = chunks;
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_slot_origin() {
+        assert_eq!(AtlasSlot(0).origin(), IVec2::ZERO);
+        assert_eq!(
+            AtlasSlot(ATLAS_SLOTS_PER_ROW + 2).origin(),
+            IVec2::new(2, 1) * CHUNK_SIZE
+        );
+    }
+
+    #[test]
+    fn test_atlas_rows_for_is_capped() {
+        assert_eq!(atlas_rows_for(10, 16, 128), 16);
+        assert_eq!(atlas_rows_for(16 * ATLAS_SLOTS_PER_ROW + 1, 16, 128), 32);
+        assert_eq!(atlas_rows_for(100 * ATLAS_SLOTS_PER_ROW, 16, 128), 128);
+        assert_eq!(atlas_rows_for(1000 * ATLAS_SLOTS_PER_ROW, 16, 128), 128);
+        assert_eq!(atlas_rows_for(100 * ATLAS_SLOTS_PER_ROW, 16, 100), 100);
+    }
+
+    #[test]
+    fn test_chunks_overlapping() {
+        let size = CHUNK_SIZE as f32;
+        assert_eq!(
+            chunks_overlapping(Vec2::splat(-size / 2.), Vec2::splat(size / 2. - 1.), size),
+            (IVec2::ZERO, IVec2::ZERO)
+        );
+        assert_eq!(
+            chunks_overlapping(Vec2::new(-size, 0.), Vec2::new(size, 0.), size),
+            (IVec2::new(-1, 0), IVec2::new(1, 0))
+        );
+    }
+
+    #[test]
+    fn test_chunk_quad_maps_x_to_atlas_rows() {
+        let atlas_size = IVec2::new(ATLAS_SLOTS_PER_ROW as i32, 2) * CHUNK_SIZE;
+        let slot = AtlasSlot(ATLAS_SLOTS_PER_ROW + 1);
+        let quad = chunk_quad(IVec2::new(1, 0), slot, 1., atlas_size);
+        let slot_width = 1. / ATLAS_SLOTS_PER_ROW as f32;
+
+        // The bottom left corner of the chunk is the top left texel of the slot
+        assert_eq!(quad[0].0, [0.5, -0.5, 0.]);
+        assert_eq!(quad[0].1, [slot_width, 0.5]);
+        // Moving along x moves down the atlas rows
+        assert_eq!(quad[1].0, [1.5, -0.5, 0.]);
+        assert_eq!(quad[1].1, [slot_width, 1.]);
+        assert_eq!(quad[3].1, [2. * slot_width, 0.5]);
+    }
+}
@@ -0,0 +1,192 @@
+use bevy::{
+    app::{App, Plugin},
+    ecs::{
+        system::{Query, Res, Resource},
+        world::{FromWorld, World},
+    },
+    math::{IRect, IVec2, Vec2Swizzles},
+    render::{
+        color::Color,
+        render_asset::RenderAssets,
+        render_resource::{Extent3d, ImageDataLayout, Origin3d},
+        renderer::RenderQueue,
+        texture::Image,
+        Extract, ExtractSchedule, RenderApp,
+    },
+};
+use enum_map::EnumMap;
+
+use crate::{
+    chunk::{Chunk, ChunkData},
+    falling_sand::ChunkPosition,
+    material::{Material, MaterialColor, MaterialColorVariation},
+    particle_grid::Particle,
+    visible_chunks::VisibleChunks,
+};
+
+use super::atlas::{AtlasSlot, ChunkAtlas};
+
+/// Renders the chunks on the CPU instead of with the compute pipeline.
+pub struct CpuRenderPlugin;
+
+impl Plugin for CpuRenderPlugin {
+    fn build(&self, app: &mut App) {
+        let render_app = app.sub_app_mut(RenderApp);
+        render_app.add_systems(ExtractSchedule, extract_chunks_on_cpu);
+    }
+}
+
+/// Color of every material for every color seed, to color particles on the CPU the same way
+/// `grid_to_texture.wgsl` does on the GPU.
+#[derive(Resource)]
+pub struct ParticlePalette(EnumMap<Material, [[u8; 4]; 256]>);
+
+impl ParticlePalette {
+    pub fn new(
+        material_colors: &MaterialColor,
+        material_color_variations: &MaterialColorVariation,
+    ) -> ParticlePalette {
+        ParticlePalette(EnumMap::from_fn(|material| {
+            let start = material_colors[material].as_linear_rgba_f32();
+            let end = material_color_variations[material].as_linear_rgba_f32();
+            std::array::from_fn(|color_seed| {
+                let t = color_seed as f32 / 255.;
+                let [r, g, b, a] = std::array::from_fn(|i| start[i] + (end[i] - start[i]) * t);
+                Color::rgba_linear(r, g, b, a).as_rgba_u8()
+            })
+        }))
+    }
+
+    pub fn particle_color(&self, particle: Particle) -> [u8; 4] {
+        self.0[particle.material()][particle.color_seed() as usize]
+    }
+}
+
+impl FromWorld for ParticlePalette {
+    fn from_world(world: &mut World) -> Self {
+        ParticlePalette::new(
+            world.resource::<MaterialColor>(),
+            world.resource::<MaterialColorVariation>(),
+        )
+    }
+}
+
+/// RGBA pixels of the particles of `chunk_data` within `rect`, in the layout of the chunk's
+/// particle grid texture, which has a row per x coordinate.
+pub fn chunk_texture_data(
+    chunk_data: &ChunkData,
+    rect: IRect,
+    palette: &ParticlePalette,
+) -> Vec<u8> {
+    (rect.min.x..=rect.max.x)
+        .flat_map(|x| (rect.min.y..=rect.max.y).map(move |y| IVec2::new(x, y)))
+        .flat_map(|position| palette.particle_color(*chunk_data.get_particle(position).unwrap()))
+        .collect()
+}
+
+/// Write the colors of the particles that changed since the last frame into the atlas slots of
+/// the visible chunks. Chunks off screen keep their changes until they become visible.
+pub fn extract_chunks_on_cpu(
+    chunk_query: Extract<Query<(&Chunk, &ChunkPosition, &AtlasSlot)>>,
+    atlas: Extract<Res<ChunkAtlas>>,
+    visible_chunks: Extract<Res<VisibleChunks>>,
+    palette: Extract<Res<ParticlePalette>>,
+    render_queue: Res<RenderQueue>,
+    images: Res<RenderAssets<Image>>,
+) {
+    // The atlas isn't prepared yet in the frame it's created or grown, the chunks are rendered
+    // into it once it is
+    let Some(atlas_image) = images.get(&atlas.image) else {
+        return;
+    };
+
+    for (chunk, position, slot) in chunk_query.iter() {
+        if !visible_chunks.contains(position.0) {
+            continue;
+        }
+        let chunk_data = &mut chunk.write().unwrap();
+        let Some(dirty_rect) = chunk_data.take_texture_dirty_rect() else {
+            continue;
+        };
+
+        // Slot rows hold the particles with the same x coordinate
+        let origin = slot.origin() + dirty_rect.min.yx();
+        let mut destination = atlas_image.texture.as_image_copy();
+        destination.origin = Origin3d {
+            x: origin.x as u32,
+            y: origin.y as u32,
+            z: 0,
+        };
+        let dirty_size = dirty_rect.size() + 1;
+        render_queue.write_texture(
+            destination,
+            &chunk_texture_data(chunk_data, dirty_rect, &palette),
+            ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(dirty_size.y as u32 * 4),
+                rows_per_image: None,
+            },
+            Extent3d {
+                width: dirty_size.y as u32,
+                height: dirty_size.x as u32,
+                depth_or_array_layers: 1,
+            },
+        );
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+
+    #[test]
+    fn test_chunk_texture_data_layout() {
+        let palette = ParticlePalette::new(
+            &MaterialColor::default(),
+            &MaterialColorVariation::default(),
+        );
+        let chunk = Chunk::new_with_material((3, 2), Material::Air, StdRng::seed_from_u64(0));
+        let mut chunk_data = chunk.write().unwrap();
+        chunk_data.set_particle_material(IVec2::new(0, 1), Material::Sand);
+        let sand = *chunk_data.get_particle(IVec2::new(0, 1)).unwrap();
+        let air = *chunk_data.get_particle(IVec2::new(1, 0)).unwrap();
+
+        let pixels = chunk_texture_data(
+            &chunk_data,
+            IRect::from_corners(IVec2::ZERO, IVec2::new(2, 1)),
+            &palette,
+        );
+        assert_eq!(pixels.len(), 3 * 2 * 4);
+        // Texture rows hold the particles with the same x coordinate
+        assert_eq!(pixels[4..8], palette.particle_color(sand));
+        assert_eq!(pixels[8..12], palette.particle_color(air));
+
+        let pixels = chunk_texture_data(
+            &chunk_data,
+            IRect::from_corners(IVec2::new(0, 1), IVec2::new(1, 1)),
+            &palette,
+        );
+        assert_eq!(pixels.len(), 2 * 4);
+        assert_eq!(pixels[0..4], palette.particle_color(sand));
+    }
+
+    #[test]
+    fn test_palette_gradient_ends() {
+        let material_colors = MaterialColor::default();
+        let material_color_variations = MaterialColorVariation::default();
+        let palette = ParticlePalette::new(&material_colors, &material_color_variations);
+
+        let mut particle = Particle::new(Material::Sand, 0);
+        assert_eq!(
+            palette.particle_color(particle),
+            material_colors[Material::Sand].as_rgba_u8()
+        );
+        particle.set_color_seed(u8::MAX);
+        assert_eq!(
+            palette.particle_color(particle),
+            material_color_variations[Material::Sand].as_rgba_u8()
+        );
+    }
+}
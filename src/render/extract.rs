@@ -1,20 +1,16 @@
 use bevy::{
-    asset::Handle,
     ecs::{
         component::Component,
         entity::Entity,
         query::With,
-        system::{Commands, Query, Res, ResMut},
+        system::{Commands, Query, Res},
     },
     hierarchy::DespawnRecursiveExt,
     render::{
         render_asset::RenderAssets,
-        render_resource::{
-            Extent3d, ImageDataLayout, TextureDescriptor, TextureDimension, TextureFormat,
-            TextureUsages, TextureView,
-        },
-        renderer::{RenderDevice, RenderQueue},
-        texture::{CachedTexture, Image, TextureCache, TextureFormatPixelInfo},
+        render_resource::{Extent3d, ImageDataLayout, Origin3d, TextureView},
+        renderer::RenderQueue,
+        texture::{Image, TextureFormatPixelInfo},
         Extract,
     },
 };
@@ -22,54 +18,63 @@ use bevy::{
 use bytemuck::cast_slice;
 use itertools::Itertools;
 
-use crate::{chunk::Chunk, consts::CHUNK_SIZE};
+use crate::{
+    chunk::Chunk,
+    consts::CHUNK_SIZE,
+    falling_sand::{ChunkParticleGridImage, ChunkPosition},
+    visible_chunks::VisibleChunks,
+};
+
+use super::atlas::{AtlasSlot, ChunkAtlas};
 
 #[derive(Component)]
 pub struct ExtractedChunkUpdate {
-    pub materials_texture: CachedTexture,
-    pub color_texture: TextureView,
+    pub materials_texture: TextureView,
+    pub slot: AtlasSlot,
 }
 
+/// Write the particles that changed since the last frame into the particle grid textures of the
+/// visible chunks, and queue those chunks to be rendered. Chunks off screen keep their changes
+/// until they become visible.
 pub fn extract(
     mut commands: Commands,
-    chunk_query: Extract<Query<(&Chunk, &Handle<Image>)>>,
-    render_device: Res<RenderDevice>,
+    chunk_query: Extract<Query<(&Chunk, &ChunkPosition, &ChunkParticleGridImage, &AtlasSlot)>>,
+    atlas: Extract<Res<ChunkAtlas>>,
+    visible_chunks: Extract<Res<VisibleChunks>>,
     render_queue: Res<RenderQueue>,
-    mut texture_cache: ResMut<TextureCache>,
     extracted_chunks_query: Query<Entity, With<ExtractedChunkUpdate>>,
     images: Res<RenderAssets<Image>>,
 ) {
     for entity in extracted_chunks_query.iter() {
         commands.entity(entity).despawn_recursive();
     }
+    // The atlas isn't prepared yet in the frame it's created or grown, the chunks are rendered
+    // into it once it is
+    if images.get(&atlas.image).is_none() {
+        return;
+    }
     let extracted_chunks = chunk_query
         .iter()
-        .flat_map(|(chunk, chunk_image)| {
-            if !chunk.read().unwrap().is_dirty() {
-                return None;
-            }
+        .filter(|(_, position, _, _)| visible_chunks.contains(position.0))
+        .flat_map(|(chunk, _, grid_image, slot)| {
+            // The textures aren't prepared yet in the frame a chunk is spawned, its changes are
+            // written once they are
+            let materials_texture_image = images.get(&grid_image.materials_texture)?;
 
-            let chunk_data = &chunk.read().unwrap();
+            let chunk_data = &mut chunk.write().unwrap();
+            let dirty_rect = chunk_data.take_texture_dirty_rect()?;
 
-            let descriptor = TextureDescriptor {
-                label: Some("chunk_update_texture"),
-                size: Extent3d {
-                    width: CHUNK_SIZE as u32,
-                    height: CHUNK_SIZE as u32,
-                    depth_or_array_layers: 1,
-                },
-                mip_level_count: 1,
-                sample_count: 1,
-                dimension: TextureDimension::D2,
-                format: TextureFormat::R32Uint,
-                usage: TextureUsages::COPY_DST | TextureUsages::TEXTURE_BINDING,
-                view_formats: &[TextureFormat::R32Uint],
+            // Texture rows hold the particles with the same x coordinate
+            let format_size = materials_texture_image.texture_format.pixel_size();
+            let mut destination = materials_texture_image.texture.as_image_copy();
+            destination.origin = Origin3d {
+                x: dirty_rect.min.y as u32,
+                y: dirty_rect.min.x as u32,
+                z: 0,
             };
-            let format_size = descriptor.format.pixel_size();
-            let material_grid_texture = texture_cache.get(&render_device, descriptor);
-
+            let dirty_size = dirty_rect.size() + 1;
             render_queue.write_texture(
-                material_grid_texture.texture.as_image_copy(),
+                destination,
                 cast_slice(
                     chunk_data
                         .particles()
@@ -78,23 +83,21 @@ pub fn extract(
                         .expect("Failed to get chunk as slice"),
                 ),
                 ImageDataLayout {
-                    offset: 0,
+                    offset: ((dirty_rect.min.x * CHUNK_SIZE + dirty_rect.min.y) as usize
+                        * format_size) as u64,
                     bytes_per_row: Some(CHUNK_SIZE as u32 * format_size as u32),
                     rows_per_image: None,
                 },
                 Extent3d {
-                    width: CHUNK_SIZE as u32,
-                    height: CHUNK_SIZE as u32,
+                    width: dirty_size.y as u32,
+                    height: dirty_size.x as u32,
                     depth_or_array_layers: 1,
                 },
             );
 
-            let color_texture_image = images.get(chunk_image)?;
-            let color_texture_view = color_texture_image.texture_view.clone();
-
             Some(ExtractedChunkUpdate {
-                materials_texture: material_grid_texture,
-                color_texture: color_texture_view,
+                materials_texture: materials_texture_image.texture_view.clone(),
+                slot: *slot,
             })
         })
         .collect_vec();
@@ -0,0 +1,262 @@
+use bevy::{
+    app::{App, FixedUpdate, Plugin, Update},
+    ecs::{
+        component::Component,
+        entity::Entity,
+        schedule::{
+            apply_deferred,
+            common_conditions::{not, resource_exists},
+            IntoSystemConfigs,
+        },
+        system::{Commands, Local, Query, Res, ResMut, Resource},
+    },
+    gizmos::gizmos::Gizmos,
+    log::info,
+    math::IVec2,
+    render::color::Color,
+};
+
+use crate::{
+    draw_tool::{tile_rect_world_bounds, CursorTilePosition, Tool, ToolState},
+    falling_sand::{ChunkCreationParams, FallingSandSet, FallingSandSettings},
+    falling_sand_grid::FallingSandGridQuery,
+    hovering_ui::UiFocused,
+    input_map::{Action, ActionInput},
+    material::Material,
+    particle_region::{ParticleRegion, RegionParticle},
+};
+
+pub struct SelectionPlugin;
+
+impl Plugin for SelectionPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Selection>()
+            .init_resource::<Clipboard>()
+            .add_systems(
+                Update,
+                (
+                    (select_region, place_paste).run_if(not(resource_exists::<UiFocused>)),
+                    clipboard_input,
+                    apply_deferred,
+                    spawn_chunks_under_paste,
+                    apply_deferred,
+                    (draw_selection_gizmos, draw_paste_preview_gizmos),
+                )
+                    .chain(),
+            )
+            .add_systems(FixedUpdate, paste_particles.before(FallingSandSet));
+    }
+}
+
+/// Tile rectangle selected with the select tool, as its minimum and maximum corner.
+#[derive(Resource, Default)]
+pub struct Selection(pub Option<(IVec2, IVec2)>);
+
+#[derive(Resource, Default)]
+pub struct Clipboard {
+    pub region: Option<ParticleRegion>,
+    /// Whether pasting leaves the world as it is where the region holds air, as for stamps.
+    pub skip_air: bool,
+}
+
+/// Region of particles waiting to be written into the world, with its lower left corner at
+/// `origin`.
+#[derive(Component)]
+pub struct Paste {
+    pub origin: IVec2,
+    pub region: ParticleRegion,
+    /// Whether the paste only replaces particles the brush can paint over, like a stroke. Cuts
+    /// and imported worlds replace everything, a cut has to clear all it copied.
+    pub brush_rules: bool,
+    /// Whether the air in the region is left out, so only its other particles are placed.
+    pub skip_air: bool,
+}
+
+#[derive(Default)]
+struct SelectionDragStart(Option<IVec2>);
+
+fn select_region(
+    action_input: ActionInput,
+    cursor_tile_position: Res<CursorTilePosition>,
+    tool_state: Res<ToolState>,
+    mut drag_start: Local<SelectionDragStart>,
+    mut selection: ResMut<Selection>,
+) {
+    if tool_state.tool != Tool::Select || !action_input.pressed(Action::Draw) {
+        drag_start.0 = None;
+        return;
+    }
+
+    if action_input.just_pressed(Action::Draw) {
+        drag_start.0 = Some(cursor_tile_position.0);
+    }
+
+    if let Some(start) = drag_start.0 {
+        let end = cursor_tile_position.0;
+        selection.0 = Some((start.min(end), start.max(end)));
+    }
+}
+
+/// Read the selected rectangle out of the world. Tiles without a chunk are copied as air.
+pub fn copy_region(grid: &FallingSandGridQuery, min: IVec2, max: IVec2) -> ParticleRegion {
+    ParticleRegion::from_fn(max - min + IVec2::ONE, |position| {
+        grid.get_particle_with_attributes(min + position)
+            .map_or_else(RegionParticle::default, |(particle, attributes)| {
+                RegionParticle {
+                    material: particle.material(),
+                    color_seed: Some(particle.color_seed()),
+                    attributes,
+                }
+            })
+    })
+}
+
+/// Lower left corner of a paste centered on the cursor.
+fn paste_origin(cursor_tile_position: IVec2, region: &ParticleRegion) -> IVec2 {
+    cursor_tile_position - region.size() / 2
+}
+
+fn clipboard_input(
+    mut commands: Commands,
+    action_input: ActionInput,
+    selection: Res<Selection>,
+    mut clipboard: ResMut<Clipboard>,
+    mut tool_state: ResMut<ToolState>,
+    grid: FallingSandGridQuery,
+) {
+    let cut = action_input.just_pressed(Action::Cut);
+    if cut || action_input.just_pressed(Action::Copy) {
+        let Some((min, max)) = selection.0 else {
+            return;
+        };
+        let region = copy_region(&grid, min, max);
+        info!("Copied {}x{} region", region.size().x, region.size().y);
+
+        if cut {
+            commands.spawn(Paste {
+                origin: min,
+                region: ParticleRegion::from_fn(region.size(), |_| RegionParticle::default()),
+                brush_rules: false,
+                skip_air: false,
+            });
+        }
+
+        clipboard.region = Some(region);
+        clipboard.skip_air = false;
+    } else if action_input.just_pressed(Action::Paste) && clipboard.region.is_some() {
+        tool_state.tool = Tool::Paste;
+    }
+
+    if tool_state.tool != Tool::Paste {
+        return;
+    }
+    let Some(region) = clipboard.region.as_mut() else {
+        return;
+    };
+
+    if action_input.just_pressed(Action::Rotate) {
+        *region = region.rotated_clockwise();
+    } else if action_input.just_pressed(Action::MirrorHorizontal) {
+        *region = region.flipped_horizontally();
+    } else if action_input.just_pressed(Action::MirrorVertical) {
+        *region = region.flipped_vertically();
+    }
+}
+
+fn place_paste(
+    mut commands: Commands,
+    action_input: ActionInput,
+    cursor_tile_position: Res<CursorTilePosition>,
+    tool_state: Res<ToolState>,
+    clipboard: Res<Clipboard>,
+) {
+    if tool_state.tool != Tool::Paste || !action_input.just_pressed(Action::Draw) {
+        return;
+    }
+    let Some(region) = &clipboard.region else {
+        return;
+    };
+
+    commands.spawn(Paste {
+        origin: paste_origin(cursor_tile_position.0, region),
+        region: region.clone(),
+        brush_rules: true,
+        skip_air: clipboard.skip_air,
+    });
+}
+
+fn spawn_chunks_under_paste(
+    mut chunk_creation_params: ChunkCreationParams,
+    paste_query: Query<&Paste>,
+) {
+    for paste in paste_query.iter() {
+        chunk_creation_params.spawn_chunks_under(
+            paste
+                .region
+                .iter()
+                .map(|(position, _)| paste.origin + position),
+        );
+    }
+}
+
+fn paste_particles(
+    mut commands: Commands,
+    mut grid: FallingSandGridQuery,
+    paste_query: Query<(Entity, &Paste)>,
+    tool_state: Res<ToolState>,
+) {
+    for (entity, paste) in paste_query.iter() {
+        for (position, particle) in paste.region.iter() {
+            if paste.skip_air && particle.material == Material::Air {
+                continue;
+            }
+            let position = paste.origin + position;
+            let can_paste = !paste.brush_rules
+                || grid
+                    .get_particle(position)
+                    .is_some_and(|particle| tool_state.can_paint_over(particle.material()));
+            if can_paste {
+                grid.set_region_particle(position, particle);
+            }
+        }
+
+        commands.entity(entity).despawn();
+    }
+}
+
+fn draw_selection_gizmos(
+    mut gizmos: Gizmos,
+    tool_state: Res<ToolState>,
+    selection: Res<Selection>,
+    falling_sand_settings: Res<FallingSandSettings>,
+) {
+    if tool_state.tool != Tool::Select {
+        return;
+    }
+    let Some((min, max)) = selection.0 else {
+        return;
+    };
+
+    let (center, size) = tile_rect_world_bounds(&falling_sand_settings, min, max);
+    gizmos.rect_2d(center, 0., size, Color::CYAN);
+}
+
+fn draw_paste_preview_gizmos(
+    mut gizmos: Gizmos,
+    tool_state: Res<ToolState>,
+    clipboard: Res<Clipboard>,
+    cursor_tile_position: Res<CursorTilePosition>,
+    falling_sand_settings: Res<FallingSandSettings>,
+) {
+    if tool_state.tool != Tool::Paste {
+        return;
+    }
+    let Some(region) = &clipboard.region else {
+        return;
+    };
+
+    let min = paste_origin(cursor_tile_position.0, region);
+    let max = min + region.size() - IVec2::ONE;
+    let (center, size) = tile_rect_world_bounds(&falling_sand_settings, min, max);
+    gizmos.rect_2d(center, 0., size, Color::ORANGE_RED);
+}
@@ -36,6 +36,14 @@ impl<T: Clone> SpatialStore<T> {
         self.get_at(position).is_some()
     }
 
+    /// Positions that hold a value.
+    pub fn occupied_positions(&self) -> impl Iterator<Item = IVec2> + '_ {
+        self.positions
+            .indexed_iter()
+            .filter(|(_, value)| value.is_some())
+            .map(|((x, y), _)| IVec2::new(x as i32, y as i32) - self.offset)
+    }
+
     pub fn add(&mut self, position: IVec2, value: T) {
         // Update the bounds and offset if necessary
         let mut new_pos = position + self.offset;
@@ -106,6 +114,12 @@ mod tests {
                     },
                 }
             }
+
+            let mut occupied_positions = store.occupied_positions().collect::<Vec<_>>();
+            let mut reference_positions = reference.keys().copied().collect::<Vec<_>>();
+            occupied_positions.sort_by_key(|p| (p.x, p.y));
+            reference_positions.sort_by_key(|p| (p.x, p.y));
+            prop_assert_eq!(occupied_positions, reference_positions);
         }
     }
 
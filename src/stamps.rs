@@ -0,0 +1,449 @@
+use std::{
+    env,
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use bevy::{
+    app::{App, Plugin, Startup, Update},
+    asset::{
+        io::Reader, Asset, AssetApp, AssetLoader, AssetServer, Assets, AsyncReadExt, Handle,
+        LoadContext,
+    },
+    ecs::{
+        change_detection::DetectChanges,
+        component::Component,
+        entity::Entity,
+        query::{Changed, With},
+        schedule::IntoSystemConfigs,
+        system::{Commands, Query, Res, ResMut, Resource},
+    },
+    hierarchy::{BuildChildren, DespawnRecursiveExt},
+    log::{info, warn},
+    math::IVec2,
+    reflect::TypePath,
+    text::Font,
+    ui::{Display, FlexDirection, FlexWrap, Interaction, PositionType, Style, Val},
+    utils::BoxedFuture,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    draw_tool::{Tool, ToolState},
+    falling_sand_grid::FallingSandGridQuery,
+    input_map::{Action, ActionInput},
+    material::Material,
+    particle_attributes::ParticleAttributeValues,
+    particle_region::{ParticleRegion, RegionParticle},
+    selection::{copy_region, Clipboard, Selection},
+    ui,
+};
+
+pub struct StampPlugin {
+    pub settings: StampSettings,
+}
+
+impl Plugin for StampPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(self.settings.clone())
+            .init_asset::<Stamp>()
+            .init_asset_loader::<StampLoader>()
+            .init_resource::<StampLibrary>()
+            .add_systems(Startup, (setup_ui, load_stamp_library))
+            .add_systems(
+                Update,
+                (save_stamp, rebuild_stamp_palette, stamp_button_system).chain(),
+            );
+    }
+}
+
+#[derive(Resource, Clone)]
+pub struct StampSettings {
+    /// Directory the stamps saved by the user are written to and loaded from.
+    pub directory: PathBuf,
+}
+
+impl Default for StampSettings {
+    fn default() -> Self {
+        Self {
+            directory: default_stamp_directory(),
+        }
+    }
+}
+
+/// `falling-sand/stamps` in the user's data directory, `$XDG_DATA_HOME` or `~/.local/share` on
+/// Linux and `%APPDATA%` on Windows. Falls back to `stamps` in the working directory when
+/// neither is set.
+fn default_stamp_directory() -> PathBuf {
+    env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("APPDATA").map(PathBuf::from))
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".local/share")))
+        .map_or_else(
+            || PathBuf::from("stamps"),
+            |data| data.join("falling-sand").join("stamps"),
+        )
+}
+
+/// Stamps that ship with the game, in `assets/stamps`. They're listed rather than read from the
+/// directory, since asset directories can't be listed on the web.
+const PREMADE_STAMPS: [&str; 3] = ["tank", "tree", "volcano"];
+const STAMP_EXTENSION: &str = "stamp.ron";
+
+/// On disk representation of a stamp. Particles are stored row by row, starting at the bottom
+/// row. Premade stamps can leave out the color seeds and attributes, they default to a new color
+/// seed and a particle at rest.
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+struct StampFile {
+    size: (u32, u32),
+    materials: Vec<Material>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    color_seeds: Vec<u8>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    velocities: Vec<(i32, i32)>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    momentums: Vec<u16>,
+}
+
+impl StampFile {
+    fn from_region(region: &ParticleRegion) -> StampFile {
+        let size = region.size();
+        let positions = (0..size.y).flat_map(|y| (0..size.x).map(move |x| IVec2::new(x, y)));
+        let particles = positions
+            .map(|position| region.get(position).unwrap())
+            .collect::<Vec<_>>();
+
+        StampFile {
+            size: (size.x as u32, size.y as u32),
+            materials: particles.iter().map(|p| p.material).collect(),
+            // Stored only if every particle has one
+            color_seeds: particles
+                .iter()
+                .map(|p| p.color_seed)
+                .collect::<Option<_>>()
+                .unwrap_or_default(),
+            velocities: particles
+                .iter()
+                .map(|p| (p.attributes.velocity.x, p.attributes.velocity.y))
+                .collect(),
+            momentums: particles.iter().map(|p| p.attributes.momentum).collect(),
+        }
+    }
+
+    fn into_region(self) -> Result<ParticleRegion, Box<dyn Error + Send + Sync>> {
+        let (width, height) = self.size;
+        let size = IVec2::new(
+            i32::try_from(width).map_err(|_| format!("stamp width {} is too large", width))?,
+            i32::try_from(height).map_err(|_| format!("stamp height {} is too large", height))?,
+        );
+        let particle_count = width
+            .checked_mul(height)
+            .ok_or_else(|| format!("stamp of size {}x{} is too large", width, height))?;
+        if self.materials.len() != particle_count as usize {
+            return Err(format!(
+                "stamp of size {}x{} has {} materials",
+                size.x,
+                size.y,
+                self.materials.len()
+            )
+            .into());
+        }
+
+        Ok(ParticleRegion::from_fn(size, |IVec2 { x, y }| {
+            let index = (y * size.x + x) as usize;
+            let velocity = self.velocities.get(index).copied().unwrap_or_default();
+            RegionParticle {
+                material: self.materials[index],
+                color_seed: self.color_seeds.get(index).copied(),
+                attributes: ParticleAttributeValues {
+                    velocity: velocity.into(),
+                    momentum: self.momentums.get(index).copied().unwrap_or_default(),
+                },
+            }
+        }))
+    }
+}
+
+pub fn save_stamp_file(path: &Path, region: &ParticleRegion) -> Result<(), Box<dyn Error>> {
+    let stamp = ron::to_string(&StampFile::from_region(region))?;
+    if let Some(directory) = path.parent() {
+        fs::create_dir_all(directory)?;
+    }
+    fs::write(path, stamp)?;
+    Ok(())
+}
+
+pub fn load_stamp_file(path: &Path) -> Result<ParticleRegion, Box<dyn Error + Send + Sync>> {
+    let stamp: StampFile = ron::from_str(&fs::read_to_string(path)?)?;
+    stamp.into_region()
+}
+
+#[derive(Asset, TypePath)]
+pub struct Stamp(pub ParticleRegion);
+
+#[derive(Default)]
+struct StampLoader;
+
+impl AssetLoader for StampLoader {
+    type Asset = Stamp;
+    type Settings = ();
+    type Error = Box<dyn Error + Send + Sync>;
+
+    fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader,
+        _settings: &'a (),
+        _load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<Stamp, Self::Error>> {
+        Box::pin(async move {
+            let mut bytes = Vec::new();
+            reader.read_to_end(&mut bytes).await?;
+            let stamp: StampFile = ron::de::from_bytes(&bytes)?;
+            Ok(Stamp(stamp.into_region()?))
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &[STAMP_EXTENSION]
+    }
+}
+
+#[derive(Debug, Clone)]
+enum StampSource {
+    Premade(Handle<Stamp>),
+    User(PathBuf),
+}
+
+#[derive(Debug, Clone)]
+struct StampEntry {
+    name: String,
+    source: StampSource,
+}
+
+#[derive(Resource, Default)]
+struct StampLibrary(Vec<StampEntry>);
+
+/// Stamps saved by the user in `directory`.
+fn read_stamp_directory(directory: &Path) -> Vec<StampEntry> {
+    let Ok(entries) = fs::read_dir(directory) else {
+        return Vec::new();
+    };
+
+    let mut stamps = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter_map(|path| {
+            let file_name = path.file_name()?.to_string_lossy();
+            let name = file_name.strip_suffix(&format!(".{}", STAMP_EXTENSION))?;
+            Some(StampEntry {
+                name: name.to_owned(),
+                source: StampSource::User(path.clone()),
+            })
+        })
+        .collect::<Vec<_>>();
+    stamps.sort_by(|a, b| a.name.cmp(&b.name));
+    stamps
+}
+
+fn load_stamp_library(
+    mut stamp_library: ResMut<StampLibrary>,
+    stamp_settings: Res<StampSettings>,
+    asset_server: Res<AssetServer>,
+) {
+    stamp_library.0 = PREMADE_STAMPS
+        .iter()
+        .map(|name| StampEntry {
+            name: name.to_string(),
+            source: StampSource::Premade(
+                asset_server.load(format!("stamps/{}.{}", name, STAMP_EXTENSION)),
+            ),
+        })
+        .collect();
+    stamp_library
+        .0
+        .extend(read_stamp_directory(&stamp_settings.directory));
+}
+
+/// First `stamp-N` name that isn't taken yet. Stamps can be renamed by renaming the file.
+fn next_stamp_name(stamp_library: &StampLibrary) -> String {
+    (1..)
+        .map(|n| format!("stamp-{}", n))
+        .find(|name| !stamp_library.0.iter().any(|stamp| &stamp.name == name))
+        .unwrap()
+}
+
+fn save_stamp(
+    action_input: ActionInput,
+    selection: Res<Selection>,
+    grid: FallingSandGridQuery,
+    mut stamp_library: ResMut<StampLibrary>,
+    stamp_settings: Res<StampSettings>,
+) {
+    if !action_input.just_pressed(Action::SaveStamp) {
+        return;
+    }
+    let Some((min, max)) = selection.0 else {
+        return;
+    };
+
+    let region = copy_region(&grid, min, max);
+    let name = next_stamp_name(&stamp_library);
+    let path = stamp_settings
+        .directory
+        .join(format!("{}.{}", name, STAMP_EXTENSION));
+    match save_stamp_file(&path, &region) {
+        Ok(()) => {
+            info!("Saved stamp {}", path.display());
+            stamp_library.0.push(StampEntry {
+                name,
+                source: StampSource::User(path),
+            });
+        }
+        Err(error) => warn!("Failed to save stamp {}: {}", path.display(), error),
+    }
+}
+
+#[derive(Component)]
+struct StampPalette;
+
+#[derive(Component)]
+struct StampButton(usize);
+
+#[derive(Resource)]
+struct StampPaletteFont(Handle<Font>);
+
+fn setup_ui(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let font = asset_server.load(ui::FONT_PATH);
+    commands.insert_resource(StampPaletteFont(font));
+
+    commands.spawn((
+        StampPalette,
+        Interaction::default(),
+        ui::panel_bundle(Style {
+            display: Display::None,
+            position_type: PositionType::Absolute,
+            bottom: Val::Px(0.0),
+            left: Val::Px(0.0),
+            flex_direction: FlexDirection::Row,
+            flex_wrap: FlexWrap::Wrap,
+            max_width: Val::Px(400.0),
+            ..ui::panel_style()
+        }),
+    ));
+}
+
+fn rebuild_stamp_palette(
+    mut commands: Commands,
+    stamp_library: Res<StampLibrary>,
+    font: Res<StampPaletteFont>,
+    mut palette_query: Query<(Entity, &mut Style), With<StampPalette>>,
+) {
+    if !stamp_library.is_changed() {
+        return;
+    }
+
+    let (palette, mut style) = palette_query.single_mut();
+    style.display = if stamp_library.0.is_empty() {
+        Display::None
+    } else {
+        Display::Flex
+    };
+
+    commands
+        .entity(palette)
+        .despawn_descendants()
+        .with_children(|parent| {
+            for (index, stamp) in stamp_library.0.iter().enumerate() {
+                ui::spawn_text_button(parent, &font.0, stamp.name.clone(), StampButton(index));
+            }
+        });
+}
+
+fn stamp_button_system(
+    interaction_query: Query<(&Interaction, &StampButton), Changed<Interaction>>,
+    stamp_library: Res<StampLibrary>,
+    stamps: Res<Assets<Stamp>>,
+    mut clipboard: ResMut<Clipboard>,
+    mut tool_state: ResMut<ToolState>,
+) {
+    for (interaction, stamp_button) in interaction_query.iter() {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        let Some(stamp) = stamp_library.0.get(stamp_button.0) else {
+            continue;
+        };
+
+        let region = match &stamp.source {
+            StampSource::Premade(handle) => stamps
+                .get(handle)
+                .map(|stamp| stamp.0.clone())
+                .ok_or_else(|| "not loaded".into()),
+            StampSource::User(path) => load_stamp_file(path),
+        };
+        match region {
+            Ok(region) => {
+                clipboard.region = Some(region);
+                clipboard.skip_air = true;
+                tool_state.tool = Tool::Paste;
+            }
+            Err(error) => warn!("Failed to load stamp {}: {}", stamp.name, error),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_stamp_file_round_trip() {
+        let region = ParticleRegion::from_fn(IVec2::new(3, 2), |IVec2 { x, y }| RegionParticle {
+            material: if y == 0 {
+                Material::Bedrock
+            } else {
+                Material::Water
+            },
+            color_seed: Some((x * 10 + y) as u8),
+            attributes: ParticleAttributeValues {
+                velocity: IVec2::new(x, -y),
+                momentum: x as u16,
+            },
+        });
+
+        let serialized = ron::to_string(&StampFile::from_region(&region)).unwrap();
+        let deserialized: StampFile = ron::from_str(&serialized).unwrap();
+        assert_eq!(deserialized.into_region().unwrap(), region);
+    }
+
+    #[test]
+    fn test_stamp_file_without_attributes() {
+        let stamp: StampFile = ron::from_str("(size: (2, 1), materials: [Bedrock, Sand])").unwrap();
+        let region = stamp.into_region().unwrap();
+        let sand = region.get(IVec2::new(1, 0)).unwrap();
+        assert_eq!(sand.material, Material::Sand);
+        assert_eq!(sand.attributes, ParticleAttributeValues::default());
+
+        let stamp: StampFile = ron::from_str("(size: (2, 2), materials: [Sand])").unwrap();
+        assert!(stamp.into_region().is_err());
+    }
+
+    #[test]
+    fn test_stamp_file_size_overflow() {
+        let stamp: StampFile = ron::from_str("(size: (65536, 65536), materials: [Sand])").unwrap();
+        assert!(stamp.into_region().is_err());
+
+        let stamp: StampFile = ron::from_str("(size: (4294967295, 1), materials: [])").unwrap();
+        assert!(stamp.into_region().is_err());
+    }
+
+    #[test]
+    fn test_premade_stamps_load() {
+        for name in PREMADE_STAMPS {
+            let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+                .join("assets/stamps")
+                .join(format!("{}.{}", name, STAMP_EXTENSION));
+            assert!(load_stamp_file(&path).is_ok(), "{}", name);
+        }
+    }
+}
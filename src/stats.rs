@@ -0,0 +1,171 @@
+use bevy::{
+    app::{App, Plugin, Startup, Update},
+    asset::AssetServer,
+    ecs::{
+        component::Component,
+        query::With,
+        schedule::{common_conditions::resource_equals, IntoSystemConfigs},
+        system::{Commands, Local, Query, Res, ResMut, Resource},
+    },
+    hierarchy::BuildChildren,
+    prelude::{default, Color},
+    text::Text,
+    time::{Real, Time},
+    ui::{
+        node_bundles::{NodeBundle, TextBundle},
+        Display, PositionType, Style, UiRect, Val,
+    },
+};
+use enum_map::EnumMap;
+use std::{fmt::Write, time::Duration};
+
+use crate::{
+    active_chunks::ActiveChunks,
+    chunk::Chunk,
+    falling_sand::SimulationPassTimings,
+    input_map::{Action, ActionInput},
+    material::{Material, MaterialIterator},
+    time_control::SimulationTick,
+    ui,
+};
+
+pub struct StatsPlugin;
+
+impl Plugin for StatsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<StatsPanel>()
+            .add_systems(Startup, setup_ui)
+            .add_systems(
+                Update,
+                (
+                    toggle_stats_panel,
+                    update_stats_panel.run_if(resource_equals(StatsPanel(true))),
+                )
+                    .chain(),
+            );
+    }
+}
+
+#[derive(Resource, Default, PartialEq)]
+struct StatsPanel(bool);
+
+/// Counting every particle is expensive, so the panel is only refreshed this often.
+const STATS_UPDATE_INTERVAL: Duration = Duration::from_millis(500);
+
+#[derive(Component)]
+struct StatsPanelNode;
+
+#[derive(Component)]
+struct StatsText;
+
+fn setup_ui(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let font = asset_server.load(ui::FONT_PATH);
+
+    commands
+        .spawn((
+            StatsPanelNode,
+            NodeBundle {
+                style: Style {
+                    display: Display::None,
+                    position_type: PositionType::Absolute,
+                    bottom: Val::Px(0.0),
+                    right: Val::Px(0.0),
+                    border: UiRect::all(Val::Px(2.0)),
+                    padding: UiRect::all(Val::Px(2.0)),
+                    margin: UiRect::all(Val::Px(2.0)),
+                    ..default()
+                },
+                border_color: ui::border_color(),
+                background_color: Color::WHITE.into(),
+                ..default()
+            },
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                StatsText,
+                TextBundle::from_section("", ui::text_style(&font)),
+            ));
+        });
+}
+
+fn toggle_stats_panel(
+    action_input: ActionInput,
+    mut stats_panel: ResMut<StatsPanel>,
+    mut panel_query: Query<&mut Style, With<StatsPanelNode>>,
+) {
+    if !action_input.just_pressed(Action::ToggleStats) {
+        return;
+    }
+
+    stats_panel.0 = !stats_panel.0;
+    for mut style in &mut panel_query {
+        style.display = if stats_panel.0 {
+            Display::Flex
+        } else {
+            Display::None
+        };
+    }
+}
+
+#[derive(Default)]
+struct StatsUpdateState {
+    last_update: Option<Duration>,
+    last_tick: u64,
+}
+
+fn count_particles<'a>(chunks: impl Iterator<Item = &'a Chunk>) -> EnumMap<Material, usize> {
+    let mut counts = EnumMap::default();
+    for chunk in chunks {
+        let chunk_data = chunk.read().unwrap();
+        for particle in chunk_data.particles().array().iter() {
+            counts[particle.material()] += 1;
+        }
+    }
+    counts
+}
+
+fn update_stats_panel(
+    mut state: Local<StatsUpdateState>,
+    time: Res<Time<Real>>,
+    simulation_tick: Res<SimulationTick>,
+    active_chunks: Res<ActiveChunks>,
+    timings: Res<SimulationPassTimings>,
+    chunk_query: Query<&Chunk>,
+    mut text_query: Query<&mut Text, With<StatsText>>,
+) {
+    let now = time.elapsed();
+    if state
+        .last_update
+        .is_some_and(|last_update| now - last_update < STATS_UPDATE_INTERVAL)
+    {
+        return;
+    }
+
+    let ticks_per_second = state.last_update.map_or(0., |last_update| {
+        simulation_tick.0.saturating_sub(state.last_tick) as f32 / (now - last_update).as_secs_f32()
+    });
+    state.last_update = Some(now);
+    state.last_tick = simulation_tick.0;
+
+    let particle_counts = count_particles(chunk_query.iter());
+
+    let mut stats = String::new();
+    writeln!(stats, "Ticks/s: {:.1}", ticks_per_second).unwrap();
+    writeln!(
+        stats,
+        "Chunks: {} spawned, {} active",
+        chunk_query.iter().len(),
+        active_chunks.iter().count()
+    )
+    .unwrap();
+    for (pass, duration) in timings.iter() {
+        writeln!(stats, "{}: {:.2}ms", pass, duration.as_secs_f64() * 1000.).unwrap();
+    }
+    for material in MaterialIterator::new() {
+        writeln!(stats, "{}: {}", material, particle_counts[material]).unwrap();
+    }
+
+    for mut text in &mut text_query {
+        text.sections[0].value = stats.trim_end().to_string();
+    }
+}
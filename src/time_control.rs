@@ -1,11 +1,30 @@
+use std::time::Duration;
+
 use bevy::{
-    app::{App, FixedUpdate, Plugin, Update},
+    app::{App, FixedUpdate, Plugin, Startup, Update},
+    asset::{AssetServer, Handle},
     ecs::{
-        schedule::Stepping,
-        system::{Res, ResMut},
+        change_detection::DetectChanges,
+        component::Component,
+        query::{Changed, With, Without},
+        schedule::{common_conditions::resource_changed, IntoSystemConfigs, Stepping},
+        system::{Commands, Query, Res, ResMut, Resource},
     },
-    input::{keyboard::KeyCode, ButtonInput},
+    hierarchy::{BuildChildren, ChildBuilder},
     log::{debug, info},
+    prelude::{default, Color},
+    text::{Font, Text, TextSection},
+    time::{Fixed, Time, Virtual},
+    ui::{
+        node_bundles::{NodeBundle, TextBundle},
+        BackgroundColor, BorderColor, FlexDirection, Interaction, PositionType, Style, UiRect, Val,
+    },
+};
+
+use crate::{
+    falling_sand::FallingSandSet,
+    input_map::{Action, ActionInput},
+    ui,
 };
 
 pub struct TimeControlPlugin;
@@ -14,17 +33,104 @@ impl Plugin for TimeControlPlugin {
     fn build(&self, app: &mut App) {
         let mut stepping = Stepping::default();
         stepping.add_schedule(FixedUpdate);
-        app.add_systems(Update, handle_input)
+        app.add_systems(Startup, setup_ui)
+            .add_systems(
+                Update,
+                (
+                    (handle_input, time_control_button_system),
+                    apply_simulation_speed.run_if(resource_changed::<SimulationSpeed>),
+                    (update_time_control_ui, update_tick_counter),
+                )
+                    .chain(),
+            )
+            .add_systems(FixedUpdate, count_ticks.after(FallingSandSet))
+            .init_resource::<SimulationSpeed>()
+            .init_resource::<SimulationTick>()
             .insert_resource(stepping);
     }
 }
 
-fn handle_input(keyboard_input: Res<ButtonInput<KeyCode>>, mut stepping: ResMut<Stepping>) {
-    if keyboard_input.just_pressed(KeyCode::Slash) {
+/// Simulation speed multipliers selectable with the speed up and slow down keys.
+pub const SPEED_STEPS: [f64; 6] = [0.25, 0.5, 1., 2., 4., 8.];
+
+#[derive(Resource, Debug, Clone, PartialEq)]
+pub struct SimulationSpeed {
+    /// How much faster than real time the `FixedUpdate` schedule runs.
+    pub multiplier: f64,
+    /// Number of `FixedUpdate` ticks a single frame may run to catch up, so a slow frame doesn't
+    /// snowball into ever longer catch-up frames. Fast speeds get more, see
+    /// `max_ticks_per_frame`.
+    pub catch_up_ticks: u32,
+}
+
+impl Default for SimulationSpeed {
+    fn default() -> Self {
+        SimulationSpeed {
+            multiplier: 1.,
+            catch_up_ticks: 8,
+        }
+    }
+}
+
+impl SimulationSpeed {
+    /// Switch to the next faster speed step, staying at the fastest step.
+    pub fn faster(&mut self) {
+        if let Some(&next) = SPEED_STEPS.iter().find(|&&step| step > self.multiplier) {
+            self.multiplier = next;
+        }
+    }
+
+    /// Switch to the next slower speed step, staying at the slowest step.
+    pub fn slower(&mut self) {
+        if let Some(&previous) = SPEED_STEPS
+            .iter()
+            .rev()
+            .find(|&&step| step < self.multiplier)
+        {
+            self.multiplier = previous;
+        }
+    }
+
+    /// Maximum number of `FixedUpdate` ticks to run in a single frame. With the default
+    /// timestep a 60 fps frame needs a little more than `multiplier` ticks, so the cap never
+    /// drops below that and one extra tick.
+    pub fn max_ticks_per_frame(&self) -> u32 {
+        self.catch_up_ticks.max(self.multiplier.ceil() as u32 + 1)
+    }
+
+    /// Largest real time delta that gets simulated in one frame, given the fixed timestep.
+    pub fn max_delta(&self, timestep: Duration) -> Duration {
+        timestep.mul_f64(self.max_ticks_per_frame() as f64 / self.multiplier)
+    }
+}
+
+fn apply_simulation_speed(
+    simulation_speed: Res<SimulationSpeed>,
+    mut virtual_time: ResMut<Time<Virtual>>,
+    fixed_time: Res<Time<Fixed>>,
+) {
+    virtual_time.set_relative_speed_f64(simulation_speed.multiplier);
+    virtual_time.set_max_delta(simulation_speed.max_delta(fixed_time.timestep()));
+    debug!("simulation speed set to {}x", simulation_speed.multiplier);
+}
+
+fn handle_input(
+    action_input: ActionInput,
+    mut stepping: ResMut<Stepping>,
+    mut simulation_speed: ResMut<SimulationSpeed>,
+) {
+    if action_input.just_pressed(Action::PrintStepping) {
         info!("{:#?}", stepping);
     }
-    // grave key to toggle stepping mode for the FixedUpdate schedule
-    if keyboard_input.just_pressed(KeyCode::Backquote) {
+
+    if action_input.just_pressed(Action::SpeedUp) {
+        simulation_speed.faster();
+    } else if action_input.just_pressed(Action::SlowDown) {
+        simulation_speed.slower();
+    }
+
+    // toggle stepping mode for the FixedUpdate schedule
+    if action_input.just_pressed(Action::ToggleStepping) {
         if stepping.is_enabled() {
             stepping.disable();
             debug!("disabled stepping");
@@ -38,12 +144,266 @@ fn handle_input(keyboard_input: Res<ButtonInput<KeyCode>>, mut stepping: ResMut<
         return;
     }
 
-    // space key will step the remainder of this frame
-    if keyboard_input.just_pressed(KeyCode::Space) {
+    // step the remainder of this frame
+    if action_input.just_pressed(Action::ContinueFrame) {
         debug!("continue");
         stepping.continue_frame();
-    } else if keyboard_input.just_pressed(KeyCode::KeyS) {
+    } else if action_input.just_pressed(Action::StepFrame) {
         debug!("stepping frame");
         stepping.step_frame();
     }
 }
+
+#[derive(Resource, Default, Debug)]
+pub struct SimulationTick(pub u64);
+
+fn count_ticks(mut simulation_tick: ResMut<SimulationTick>) {
+    simulation_tick.0 += 1;
+}
+
+#[derive(Component, Clone, Copy, PartialEq)]
+enum TimeControlButton {
+    PlayPause,
+    Step,
+    Speed(f64),
+}
+
+#[derive(Component)]
+struct PlayPauseText;
+
+#[derive(Component)]
+struct SpeedIndicatorText;
+
+#[derive(Component)]
+struct TickCounterText;
+
+fn setup_ui(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let font = asset_server.load(ui::FONT_PATH);
+    let node_style = Style {
+        position_type: PositionType::Absolute,
+        top: Val::Px(0.0),
+        right: Val::Px(0.0),
+        ..ui::panel_style()
+    };
+
+    commands
+        .spawn((Interaction::default(), ui::panel_bundle(node_style)))
+        .with_children(|parent| {
+            spawn_step_controls(parent, &font);
+            spawn_speed_picker(parent, &font);
+            spawn_status_text(parent, &font);
+        });
+}
+
+fn spawn_step_controls(parent: &mut ChildBuilder, font: &Handle<Font>) {
+    parent
+        .spawn(NodeBundle {
+            style: Style {
+                flex_direction: FlexDirection::Row,
+                ..default()
+            },
+            ..default()
+        })
+        .with_children(|parent| {
+            parent
+                .spawn((TimeControlButton::PlayPause, ui::button_bundle()))
+                .with_children(|parent| {
+                    parent.spawn((
+                        PlayPauseText,
+                        TextBundle::from_section("Pause", ui::text_style(font)),
+                    ));
+                });
+
+            ui::spawn_text_button(parent, font, "Step", TimeControlButton::Step);
+        });
+}
+
+fn spawn_speed_picker(parent: &mut ChildBuilder, font: &Handle<Font>) {
+    parent
+        .spawn(NodeBundle {
+            style: Style {
+                flex_direction: FlexDirection::Row,
+                ..default()
+            },
+            ..default()
+        })
+        .with_children(|parent| {
+            for speed in SPEED_STEPS {
+                ui::spawn_text_button(
+                    parent,
+                    font,
+                    format!("{}x", speed),
+                    TimeControlButton::Speed(speed),
+                );
+            }
+        });
+}
+
+fn spawn_status_text(parent: &mut ChildBuilder, font: &Handle<Font>) {
+    let style = Style {
+        margin: UiRect::all(Val::Px(2.0)),
+        padding: UiRect::all(Val::Px(2.0)),
+        ..default()
+    };
+    parent.spawn((
+        SpeedIndicatorText,
+        TextBundle::from_sections([
+            TextSection::new("Speed:", ui::text_style(font)),
+            TextSection::new("", ui::text_style(font)),
+        ])
+        .with_style(style.clone()),
+    ));
+    parent.spawn((
+        TickCounterText,
+        TextBundle::from_sections([
+            TextSection::new("Tick:", ui::text_style(font)),
+            TextSection::new("0", ui::text_style(font)),
+        ])
+        .with_style(style),
+    ));
+}
+
+fn time_control_button_system(
+    mut button_query: Query<
+        (&Interaction, &TimeControlButton, &mut BorderColor),
+        Changed<Interaction>,
+    >,
+    mut stepping: ResMut<Stepping>,
+    mut simulation_speed: ResMut<SimulationSpeed>,
+) {
+    for (interaction, button, mut border_color) in button_query.iter_mut() {
+        match *interaction {
+            Interaction::Pressed => {
+                border_color.0 = Color::BLACK;
+                match *button {
+                    TimeControlButton::PlayPause => {
+                        if stepping.is_enabled() {
+                            stepping.disable();
+                        } else {
+                            stepping.enable();
+                        }
+                    }
+                    TimeControlButton::Step => {
+                        if !stepping.is_enabled() {
+                            stepping.enable();
+                        }
+                        stepping.continue_frame();
+                    }
+                    TimeControlButton::Speed(speed) => {
+                        simulation_speed.multiplier = speed;
+                    }
+                }
+            }
+            Interaction::Hovered => {
+                border_color.0 = Color::GRAY;
+            }
+            Interaction::None => {
+                border_color.0 = BorderColor::default().0;
+            }
+        }
+    }
+}
+
+fn update_time_control_ui(
+    simulation_speed: Res<SimulationSpeed>,
+    stepping: Res<Stepping>,
+    mut speed_button_query: Query<(&mut BackgroundColor, &TimeControlButton)>,
+    mut speed_indicator_query: Query<&mut Text, With<SpeedIndicatorText>>,
+    mut play_pause_query: Query<&mut Text, (With<PlayPauseText>, Without<SpeedIndicatorText>)>,
+) {
+    if !simulation_speed.is_changed() && !stepping.is_changed() {
+        return;
+    }
+
+    for (mut background_color, button) in speed_button_query.iter_mut() {
+        let selected = match *button {
+            TimeControlButton::PlayPause => stepping.is_enabled(),
+            TimeControlButton::Step => false,
+            TimeControlButton::Speed(speed) => speed == simulation_speed.multiplier,
+        };
+        background_color.0 = if selected {
+            Color::SILVER
+        } else {
+            BackgroundColor::default().0
+        };
+    }
+
+    for mut text in &mut speed_indicator_query {
+        text.sections[1].value = if stepping.is_enabled() {
+            format!("{}x (stepping)", simulation_speed.multiplier)
+        } else {
+            format!("{}x", simulation_speed.multiplier)
+        };
+    }
+
+    for mut text in &mut play_pause_query {
+        text.sections[0].value = if stepping.is_enabled() {
+            "Play".to_string()
+        } else {
+            "Pause".to_string()
+        };
+    }
+}
+
+fn update_tick_counter(
+    simulation_tick: Res<SimulationTick>,
+    mut tick_counter_query: Query<&mut Text, With<TickCounterText>>,
+) {
+    if !simulation_tick.is_changed() {
+        return;
+    }
+
+    for mut text in &mut tick_counter_query {
+        text.sections[1].value = simulation_tick.0.to_string();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_simulation_speed_steps() {
+        let mut simulation_speed = SimulationSpeed::default();
+
+        simulation_speed.faster();
+        assert_eq!(simulation_speed.multiplier, 2.);
+
+        for _ in 0..SPEED_STEPS.len() {
+            simulation_speed.faster();
+        }
+        assert_eq!(simulation_speed.multiplier, 8.);
+
+        for _ in 0..SPEED_STEPS.len() {
+            simulation_speed.slower();
+        }
+        assert_eq!(simulation_speed.multiplier, 0.25);
+    }
+
+    #[test]
+    fn test_simulation_speed_max_delta() {
+        let timestep = Duration::from_micros(15625);
+        let simulation_speed = SimulationSpeed {
+            multiplier: 2.,
+            catch_up_ticks: 8,
+        };
+        assert_eq!(simulation_speed.max_delta(timestep), timestep * 4);
+
+        let simulation_speed = SimulationSpeed {
+            multiplier: 8.,
+            catch_up_ticks: 4,
+        };
+        assert_eq!(simulation_speed.max_ticks_per_frame(), 9);
+        assert_eq!(
+            simulation_speed.max_delta(timestep),
+            timestep.mul_f64(9. / 8.)
+        );
+
+        // The fastest step gets enough ticks for a 60 fps frame
+        let simulation_speed = SimulationSpeed {
+            multiplier: SPEED_STEPS[SPEED_STEPS.len() - 1],
+            ..default()
+        };
+        assert!(simulation_speed.max_delta(timestep) > Duration::from_secs_f64(1. / 60.));
+    }
+}
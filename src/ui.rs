@@ -0,0 +1,89 @@
+use bevy::{
+    asset::Handle,
+    ecs::bundle::Bundle,
+    hierarchy::{BuildChildren, ChildBuilder},
+    prelude::{default, Color},
+    text::{Font, TextStyle},
+    ui::{
+        node_bundles::{ButtonBundle, NodeBundle, TextBundle},
+        BorderColor, Display, FlexDirection, FlexWrap, Style, UiRect, Val,
+    },
+};
+
+/// Font of all the UI panels.
+pub const FONT_PATH: &str = "fonts/PublicPixel-z84yD.ttf";
+
+pub fn text_style(font: &Handle<Font>) -> TextStyle {
+    TextStyle {
+        font: font.clone(),
+        color: Color::BLACK,
+        ..default()
+    }
+}
+
+pub fn border_color() -> BorderColor {
+    (Color::GRAY * 1.8).into()
+}
+
+/// Bordered column of controls.
+pub fn panel_style() -> Style {
+    Style {
+        border: UiRect::all(Val::Px(2.0)),
+        display: Display::Flex,
+        flex_direction: FlexDirection::Column,
+        padding: UiRect::all(Val::Px(2.0)),
+        margin: UiRect::all(Val::Px(2.0)),
+        ..default()
+    }
+}
+
+pub fn panel_bundle(style: Style) -> NodeBundle {
+    NodeBundle {
+        style,
+        border_color: border_color(),
+        background_color: Color::WHITE.into(),
+        ..default()
+    }
+}
+
+pub fn button_style() -> Style {
+    Style {
+        margin: UiRect::all(Val::Px(2.0)),
+        padding: UiRect::all(Val::Px(2.0)),
+        border: UiRect::all(Val::Px(2.0)),
+        ..default()
+    }
+}
+
+pub fn button_bundle() -> ButtonBundle {
+    ButtonBundle {
+        style: button_style(),
+        border_color: border_color(),
+        background_color: Color::WHITE.into(),
+        ..default()
+    }
+}
+
+/// Row of buttons that wraps when it gets wider than the panel.
+pub fn button_row_style() -> Style {
+    Style {
+        flex_direction: FlexDirection::Row,
+        flex_wrap: FlexWrap::Wrap,
+        max_width: Val::Px(300.0),
+        ..default()
+    }
+}
+
+/// Spawn a button with `components`, labeled with `label`.
+pub fn spawn_text_button(
+    parent: &mut ChildBuilder,
+    font: &Handle<Font>,
+    label: impl Into<String>,
+    components: impl Bundle,
+) {
+    parent
+        .spawn((components, button_bundle()))
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section(label, text_style(font)));
+        });
+}
@@ -0,0 +1,124 @@
+use bevy::{
+    core_pipeline::core_2d::Camera2d,
+    ecs::{
+        change_detection::DetectChangesMut,
+        query::With,
+        system::{Query, Res, ResMut, Resource},
+    },
+    math::IVec2,
+    render::{camera::OrthographicProjection, extract_resource::ExtractResource},
+    transform::components::Transform,
+};
+
+use crate::{
+    consts::CHUNK_SIZE, falling_sand::FallingSandSettings, render::atlas::chunks_overlapping,
+};
+
+/// Range of chunk positions on screen. Only these chunks are drawn, and their textures are only
+/// updated while they are visible.
+#[derive(Resource, Clone, Copy, Debug, PartialEq, Eq, ExtractResource)]
+pub struct VisibleChunks {
+    pub min: IVec2,
+    pub max: IVec2,
+}
+
+impl Default for VisibleChunks {
+    /// Nothing is visible until there is a camera.
+    fn default() -> Self {
+        VisibleChunks {
+            min: IVec2::ONE,
+            max: IVec2::ZERO,
+        }
+    }
+}
+
+impl VisibleChunks {
+    pub fn contains(&self, position: IVec2) -> bool {
+        position.cmpge(self.min).all() && position.cmple(self.max).all()
+    }
+
+    /// Distance in chunks from `position` to the closest visible chunk, counting diagonal steps
+    /// as one.
+    pub fn distance(&self, position: IVec2) -> i32 {
+        let outside = (self.min - position)
+            .max(position - self.max)
+            .max(IVec2::ZERO);
+        outside.max_element()
+    }
+
+    /// Whether the active chunk at `position` is simulated in `tick`, the `SimulationTick` of the
+    /// tick about to run. Chunks further than
+    /// `distant_chunk_distance` from the screen are only simulated every
+    /// `distant_tick_interval` ticks.
+    // `u64::is_multiple_of` is newer than the toolchain pinned in flake.nix
+    #[allow(unknown_lints, clippy::manual_is_multiple_of)]
+    pub fn is_simulated(
+        &self,
+        position: IVec2,
+        tick: u64,
+        falling_sand_settings: &FallingSandSettings,
+    ) -> bool {
+        self.distance(position) <= falling_sand_settings.distant_chunk_distance
+            || tick % falling_sand_settings.distant_tick_interval.max(1) as u64 == 0
+    }
+}
+
+pub fn update_visible_chunks(
+    mut visible_chunks: ResMut<VisibleChunks>,
+    falling_sand_settings: Res<FallingSandSettings>,
+    camera_query: Query<(&Transform, &OrthographicProjection), With<Camera2d>>,
+) {
+    let Ok((transform, projection)) = camera_query.get_single() else {
+        return;
+    };
+
+    let chunk_world_size = (CHUNK_SIZE * falling_sand_settings.tile_size as i32) as f32;
+    let center = transform.translation.truncate();
+    let (min, max) = chunks_overlapping(
+        center + projection.area.min,
+        center + projection.area.max,
+        chunk_world_size,
+    );
+    visible_chunks.set_if_neq(VisibleChunks { min, max });
+}
+
+#[cfg(test)]
+mod test {
+    use bevy::utils::default;
+
+    use super::*;
+
+    #[test]
+    fn test_distance() {
+        let visible_chunks = VisibleChunks {
+            min: IVec2::new(-1, -1),
+            max: IVec2::new(2, 1),
+        };
+        assert!(visible_chunks.contains(IVec2::new(2, -1)));
+        assert!(!visible_chunks.contains(IVec2::new(3, 0)));
+        assert_eq!(visible_chunks.distance(IVec2::new(0, 1)), 0);
+        assert_eq!(visible_chunks.distance(IVec2::new(5, 0)), 3);
+        assert_eq!(visible_chunks.distance(IVec2::new(-3, 3)), 2);
+        assert_eq!(VisibleChunks::default().distance(IVec2::ZERO), 1);
+    }
+
+    #[test]
+    fn test_distant_chunks_are_simulated_less_often() {
+        let visible_chunks = VisibleChunks {
+            min: IVec2::ZERO,
+            max: IVec2::ZERO,
+        };
+        let falling_sand_settings = FallingSandSettings {
+            distant_chunk_distance: 2,
+            distant_tick_interval: 4,
+            ..default()
+        };
+        let simulated_ticks = |position| {
+            (0..8)
+                .filter(|&tick| visible_chunks.is_simulated(position, tick, &falling_sand_settings))
+                .count()
+        };
+        assert_eq!(simulated_ticks(IVec2::new(2, -2)), 8);
+        assert_eq!(simulated_ticks(IVec2::new(3, 0)), 2);
+    }
+}